@@ -0,0 +1,111 @@
+//! Adaptive concurrency for `--adaptive`.
+//!
+//! A congestion-control style loop: the scan starts with a few attempts in
+//! flight, and after every round (as many finished ports as the current
+//! limit) it looks at the share of timeouts in that round. While timeouts
+//! stay rare the limit grows by a quarter, when they spike it is halved, and
+//! in between it holds. The limit never exceeds the configured concurrency.
+//!
+//! Slots are semaphore permits: growing adds permits, and shrinking retires
+//! permits as the attempts holding them finish.
+
+use std::sync::Mutex; // Round counters
+use tokio::sync::{Semaphore, SemaphorePermit}; // Attempt slots
+
+/// Limit the scan starts with (capped by the maximum)
+pub const ADAPTIVE_START: usize = 8;
+/// Grow the limit when at most this share of a round timed out
+const GROW_BELOW: f64 = 0.02;
+/// Halve the limit when more than this share of a round timed out
+const BACK_OFF_ABOVE: f64 = 0.10;
+
+/// Counters shared by all attempts
+struct State {
+    /// Attempts allowed in flight right now
+    limit: usize,
+    /// Highest limit reached
+    peak: usize,
+    /// Permits still to retire after a back-off
+    debt: usize,
+    /// Attempts finished in the current round
+    round_done: usize,
+    /// Timeouts in the current round
+    round_timeouts: usize,
+}
+
+/// Dynamically sized pool of attempt slots
+pub struct AdaptiveConcurrency {
+    /// One permit per attempt allowed in flight
+    slots: Semaphore,
+    /// Upper bound for the limit
+    max: usize,
+    /// Current limit and round counters
+    state: Mutex<State>,
+}
+
+impl AdaptiveConcurrency {
+    /// Starts at `ADAPTIVE_START` attempts in flight, growing to at most `max`.
+    pub fn new(max: usize) -> Self {
+        let max = max.max(1);
+        let start = ADAPTIVE_START.min(max);
+        Self {
+            slots: Semaphore::new(start),
+            max,
+            state: Mutex::new(State { limit: start, peak: start, debt: 0, round_done: 0, round_timeouts: 0 }),
+        }
+    }
+
+    /// Waits for a free slot; slots are handed out in request order.
+    pub async fn acquire(&self) -> SemaphorePermit<'_> {
+        self.slots.acquire().await.expect("slot semaphore is never closed")
+    }
+
+    /// Returns a slot after an attempt, feeding back whether it timed out,
+    /// and adjusts the limit at the end of each round.
+    pub fn finish(&self, permit: SemaphorePermit<'_>, timed_out: bool) {
+        let Ok(mut state) = self.state.lock() else { return };
+
+        // Retire this slot instead of returning it while shrinking
+        if state.debt > 0 {
+            state.debt -= 1;
+            permit.forget();
+        } else {
+            drop(permit);
+        }
+
+        state.round_done += 1;
+        state.round_timeouts += usize::from(timed_out);
+        if state.round_done < state.limit {
+            return;
+        }
+
+        let share = state.round_timeouts as f64 / state.round_done as f64;
+        state.round_done = 0;
+        state.round_timeouts = 0;
+        if share <= GROW_BELOW && state.limit < self.max {
+            let grown = (state.limit + (state.limit / 4).max(1)).min(self.max);
+            // Growth first pays off any retirements still pending
+            let mut added = grown - state.limit;
+            let paid = added.min(state.debt);
+            state.debt -= paid;
+            added -= paid;
+            self.slots.add_permits(added);
+            state.limit = grown;
+            state.peak = state.peak.max(grown);
+        } else if share > BACK_OFF_ABOVE && state.limit > 1 {
+            let halved = state.limit / 2;
+            state.debt += state.limit - halved;
+            state.limit = halved;
+        }
+    }
+
+    /// Current limit on attempts in flight
+    pub fn limit(&self) -> usize {
+        self.state.lock().map_or(0, |state| state.limit)
+    }
+
+    /// Highest limit reached so far
+    pub fn peak(&self) -> usize {
+        self.state.lock().map_or(0, |state| state.peak)
+    }
+}
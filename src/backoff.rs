@@ -0,0 +1,165 @@
+//! Pauses between retries of a port (`--backoff-mode`, `--backoff-max`).
+//!
+//! Retry `n` (counting from 0) has a ceiling of `base * 2^n`, capped at the
+//! maximum. `exponential` waits exactly that long; `full-jitter` waits a
+//! random time between zero and the ceiling, as in AWS's "Exponential
+//! Backoff And Jitter", so retries of many ports that failed together do
+//! not all hit the target again at the same moment. `fixed` always waits
+//! the base.
+//!
+//! Jittered delays are drawn from the seed, the address, the port and the
+//! retry number, so a run with the same seed repeats the same delays no
+//! matter in which order the attempts finish.
+
+use clap::ValueEnum; // `--backoff-mode` values
+use rand::rngs::StdRng; // Reproducible jitter
+use rand::{Rng, SeedableRng}; // Drawing a delay from a seed
+use std::hash::{DefaultHasher, Hash, Hasher}; // Seed per address, port and retry
+use std::net::IpAddr; // Represents an IP address
+use std::time::Duration; // Delays
+
+/// Longest pause between retries by default
+pub const DEFAULT_BACKOFF_MAX: Duration = Duration::from_secs(2);
+
+/// How the pause grows from one retry to the next
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum BackoffMode {
+    /// The base delay before every retry
+    Fixed,
+    /// The base delay, doubled for every further retry (default)
+    Exponential,
+    /// A random delay up to what `exponential` would wait
+    FullJitter,
+}
+
+impl BackoffMode {
+    /// Name as accepted by `--backoff-mode`
+    pub fn name(self) -> &'static str {
+        match self {
+            BackoffMode::Fixed => "fixed",
+            BackoffMode::Exponential => "exponential",
+            BackoffMode::FullJitter => "full-jitter",
+        }
+    }
+}
+
+/// Delay policy for retries
+#[derive(Clone, Copy, Debug)]
+pub struct Backoff {
+    /// How the delay grows
+    pub mode: BackoffMode,
+    /// Delay before the first retry, and the unit of the growth
+    pub base: Duration,
+    /// Longest delay
+    pub max: Duration,
+    /// Seed of the jitter
+    pub seed: u64,
+}
+
+impl Backoff {
+    /// `mode` growing from `base` up to `max`, with jitter drawn from `seed`.
+    pub fn new(mode: BackoffMode, base: Duration, max: Duration, seed: u64) -> Self {
+        Self { mode, base, max, seed }
+    }
+
+    /// Longest pause before retry `retry` (from 0): `base * 2^retry`,
+    /// capped at the maximum.
+    pub fn ceiling(&self, retry: usize) -> Duration {
+        let factor = u32::try_from(retry).ok().and_then(|retry| 1u32.checked_shl(retry)).unwrap_or(u32::MAX);
+        self.base.saturating_mul(factor).min(self.max)
+    }
+
+    /// Longest total pause before `retries` retries of one port, for the
+    /// worst-case estimate.
+    pub fn total(&self, retries: usize) -> Duration {
+        (0..retries)
+            .map(|retry| if self.mode == BackoffMode::Fixed { self.base.min(self.max) } else { self.ceiling(retry) })
+            .fold(Duration::ZERO, Duration::saturating_add)
+    }
+
+    /// Pause before retry `retry` (from 0) of `port` on `ip`.
+    pub fn delay(&self, ip: IpAddr, port: u16, retry: usize) -> Duration {
+        match self.mode {
+            BackoffMode::Fixed => self.base.min(self.max),
+            BackoffMode::Exponential => self.ceiling(retry),
+            BackoffMode::FullJitter => {
+                let mut hasher = DefaultHasher::new();
+                (self.seed, ip, port, retry).hash(&mut hasher);
+                let mut rng = StdRng::seed_from_u64(hasher.finish());
+                let ceiling = self.ceiling(retry).as_micros() as u64;
+                Duration::from_micros(rng.random_range(0..=ceiling))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    fn backoff(mode: BackoffMode) -> Backoff {
+        Backoff::new(mode, Duration::from_millis(100), Duration::from_secs(2), 42)
+    }
+
+    #[test]
+    fn fixed_waits_the_base() {
+        let backoff = backoff(BackoffMode::Fixed);
+        for retry in 0..10 {
+            assert_eq!(backoff.delay(IP, 80, retry), Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn exponential_doubles_up_to_the_max() {
+        let backoff = backoff(BackoffMode::Exponential);
+        let delays: Vec<u128> = (0..7).map(|retry| backoff.delay(IP, 80, retry).as_millis()).collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1600, 2000, 2000]);
+    }
+
+    #[test]
+    fn total_adds_up_the_longest_pauses() {
+        assert_eq!(backoff(BackoffMode::Fixed).total(3), Duration::from_millis(300));
+        assert_eq!(backoff(BackoffMode::Exponential).total(3), Duration::from_millis(700));
+        assert_eq!(backoff(BackoffMode::FullJitter).total(6), Duration::from_millis(5100));
+        assert_eq!(backoff(BackoffMode::Exponential).total(0), Duration::ZERO);
+    }
+
+    #[test]
+    fn ceiling_does_not_overflow() {
+        let backoff = Backoff::new(BackoffMode::Exponential, Duration::from_secs(1), Duration::MAX, 0);
+        assert_eq!(backoff.ceiling(usize::MAX), Duration::from_secs(u64::from(u32::MAX)));
+    }
+
+    #[test]
+    fn full_jitter_stays_within_the_ceiling() {
+        let backoff = backoff(BackoffMode::FullJitter);
+        for retry in 0..10 {
+            let ceiling = backoff.ceiling(retry);
+            for port in 1..200 {
+                assert!(backoff.delay(IP, port, retry) <= ceiling, "retry {retry} of port {port}");
+            }
+        }
+    }
+
+    #[test]
+    fn full_jitter_spreads_the_delays() {
+        let backoff = backoff(BackoffMode::FullJitter);
+        let delays: Vec<Duration> = (1..200).map(|port| backoff.delay(IP, port, 3)).collect();
+        let ceiling = backoff.ceiling(3);
+        assert!(delays.iter().any(|&delay| delay < ceiling / 4));
+        assert!(delays.iter().any(|&delay| delay > ceiling * 3 / 4));
+    }
+
+    #[test]
+    fn full_jitter_repeats_with_the_same_seed() {
+        let first = backoff(BackoffMode::FullJitter);
+        let again = backoff(BackoffMode::FullJitter);
+        let other = Backoff { seed: 7, ..first };
+        let delays = |backoff: &Backoff| (1..50).map(|port| backoff.delay(IP, port, 2)).collect::<Vec<_>>();
+        assert_eq!(delays(&first), delays(&again));
+        assert_ne!(delays(&first), delays(&other));
+    }
+}
@@ -0,0 +1,68 @@
+//! Banner grabbing for `--banner`.
+//!
+//! Many services greet a new connection before the client says anything
+//! (SSH version strings, SMTP and FTP greetings, ...). After a successful
+//! connect the scanner waits briefly for that greeting on the same
+//! connection and keeps its first line as text. `--banner-bytes` and
+//! `--read-timeout` bound how much is read and for how long.
+//!
+//! `--wait-before-close` holds the connection for a set time instead and
+//! keeps whatever the service sends until then.
+
+use tokio::io::AsyncReadExt; // Async reads on the stream
+use tokio::net::TcpStream; // Connected scan socket
+use tokio::time::{Duration, Instant, timeout_at}; // Bound the wait for a greeting
+
+/// Default maximum number of banner bytes read
+pub const DEFAULT_BYTES: usize = 1024;
+/// Default time to wait for the server to speak first
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(2000);
+
+/// Reads the greeting of a freshly connected `stream`, decoded as lossy
+/// UTF-8 with surrounding whitespace trimmed.
+///
+/// Reading stops at the first newline, after `max_bytes` or when `wait` is
+/// over, whichever comes first, so a chatty service cannot make the scan
+/// slurp megabytes. Services that wait for the client, closed connections
+/// and read errors before any data all yield `None`.
+pub async fn read(mut stream: TcpStream, max_bytes: usize, wait: Duration) -> Option<String> {
+    let deadline = Instant::now() + wait;
+    let mut buf = vec![0; max_bytes];
+    let mut len = 0;
+    while len < max_bytes {
+        match timeout_at(deadline, stream.read(&mut buf[len..])).await {
+            Ok(Ok(n)) if n > 0 => {
+                // Keep the line, up to and without its newline
+                if let Some(end) = buf[len..len + n].iter().position(|&b| b == b'\n') {
+                    len += end;
+                    break;
+                }
+                len += n;
+            }
+            _ => break,
+        }
+    }
+    let banner = String::from_utf8_lossy(&buf[..len]).trim().to_string();
+    (!banner.is_empty()).then_some(banner)
+}
+
+/// Keeps a freshly connected `stream` open for `wait`, collecting any data
+/// the service sends meanwhile (at most `max_bytes`), then closes it. The
+/// data is decoded like `read`, newlines included; a silent service yields
+/// `None`.
+///
+/// The connection is dropped early if the peer closes it, a read fails or
+/// `max_bytes` arrived, so the wait never exceeds `wait`.
+pub async fn hold(mut stream: TcpStream, wait: Duration, max_bytes: usize) -> Option<String> {
+    let deadline = Instant::now() + wait;
+    let mut buf = vec![0; max_bytes];
+    let mut len = 0;
+    while len < max_bytes {
+        match timeout_at(deadline, stream.read(&mut buf[len..])).await {
+            Ok(Ok(n)) if n > 0 => len += n,
+            _ => break,
+        }
+    }
+    let banner = String::from_utf8_lossy(&buf[..len]).trim().to_string();
+    (!banner.is_empty()).then_some(banner)
+}
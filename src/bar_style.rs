@@ -0,0 +1,53 @@
+//! Presets for the terminal progress bar (`--progress-style`).
+//!
+//! `bar` is the original look; `spinner` drops the determinate bar for
+//! terminals or logs where it renders badly, and `plain` keeps just the
+//! counts and the ETA.
+
+use clap::ValueEnum; // `--progress-style` values
+use indicatif::ProgressStyle; // Bar templates
+
+/// Progress bar preset
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BarStyle {
+    /// Elapsed time, a bar, the counts and the ETA (default)
+    Bar,
+    /// A spinner with the elapsed time and the counts
+    Spinner,
+    /// Counts, percentage and ETA as text only
+    Plain,
+}
+
+impl BarStyle {
+    /// Name as accepted by `--progress-style`
+    pub fn name(self) -> &'static str {
+        match self {
+            BarStyle::Bar => "bar",
+            BarStyle::Spinner => "spinner",
+            BarStyle::Plain => "plain",
+        }
+    }
+
+    /// The indicatif style for the preset, falling back to the library
+    /// default with a warning if its template fails to parse
+    pub fn style(self) -> ProgressStyle {
+        match self {
+            BarStyle::Bar => {
+                with_template(ProgressStyle::default_bar, "[{elapsed_precise}] {bar:40.red/cyan} {pos}/{len} ({eta}) {msg}")
+                    .progress_chars("=>-")
+            }
+            BarStyle::Spinner => with_template(ProgressStyle::default_spinner, "{spinner} [{elapsed_precise}] {pos}/{len} {msg}"),
+            BarStyle::Plain => with_template(ProgressStyle::default_bar, "{pos}/{len} ({percent}%), ETA {eta} {msg}"),
+        }
+    }
+}
+
+/// Applies `template` to the `base` style. A rejected template is reported
+/// on stderr rather than swallowed, and the scan goes on with the plain
+/// `base` style: the bar is cosmetic and not worth aborting a scan for.
+fn with_template(base: fn() -> ProgressStyle, template: &str) -> ProgressStyle {
+    base().template(template).unwrap_or_else(|e| {
+        eprintln!("Warning: progress bar template `{template}` was rejected ({e}); using the default bar");
+        base()
+    })
+}
@@ -0,0 +1,70 @@
+//! Per-host timeout calibration from a measured round-trip time.
+//!
+//! A refused or accepted connect takes about one network round trip, so the
+//! fastest such attempt is a good RTT estimate. Nearby hosts then get a short
+//! connect timeout and distant ones keep a longer budget.
+
+use port_sniffer::route; // Source-bound connections
+use std::net::{IpAddr, SocketAddr}; // Probe targets and source address
+use tokio::time::{Duration, Instant, timeout}; // Timing the probes
+
+/// Number of ports from the scan list used to measure the RTT
+pub const RTT_PROBES: usize = 3;
+/// Timeout multiple of the measured RTT
+const RTT_MULTIPLIER: u32 = 4;
+/// Fixed allowance added on top, to absorb jitter on very fast links
+const RTT_MARGIN: Duration = Duration::from_millis(50);
+/// Calibrated timeouts never go below this
+const MIN_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Connects to each of `targets` once (from `source`, if given) and returns
+/// the fastest time to an answer (accepted or refused), or `None` if every
+/// attempt hung past `budget`.
+pub async fn measure_rtt(targets: &[SocketAddr], source: Option<IpAddr>, budget: Duration) -> Option<Duration> {
+    let mut best: Option<Duration> = None;
+    for &target in targets {
+        let started = Instant::now();
+        if timeout(budget, route::tcp_connect(target, source)).await.is_ok() {
+            let rtt = started.elapsed();
+            best = Some(best.map_or(rtt, |b| b.min(rtt)));
+        }
+    }
+    best
+}
+
+/// Derives a connect timeout from a measured RTT: `RTT_MULTIPLIER * rtt +
+/// RTT_MARGIN`, clamped to `[MIN_TIMEOUT, max]`.
+pub fn timeout_for_rtt(rtt: Duration, max: Duration) -> Duration {
+    rtt.saturating_mul(RTT_MULTIPLIER).saturating_add(RTT_MARGIN).clamp(MIN_TIMEOUT, max.max(MIN_TIMEOUT))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fast_links_get_the_minimum() {
+        assert_eq!(timeout_for_rtt(Duration::ZERO, Duration::from_secs(1)), MIN_TIMEOUT);
+        assert_eq!(timeout_for_rtt(Duration::from_millis(5), Duration::from_secs(1)), MIN_TIMEOUT);
+    }
+
+    #[test]
+    fn slow_links_are_capped_at_the_maximum() {
+        let max = Duration::from_millis(500);
+        assert_eq!(timeout_for_rtt(Duration::from_millis(200), max), max);
+        assert_eq!(timeout_for_rtt(Duration::MAX, max), max);
+    }
+
+    #[test]
+    fn maximum_below_the_minimum_keeps_the_minimum() {
+        assert_eq!(timeout_for_rtt(Duration::from_millis(200), Duration::from_millis(10)), MIN_TIMEOUT);
+    }
+
+    #[test]
+    fn multiplier_and_margin_in_between() {
+        // 4 * 30ms + 50ms
+        assert_eq!(timeout_for_rtt(Duration::from_millis(30), Duration::from_secs(1)), Duration::from_millis(170));
+        // 4 * 100ms + 50ms
+        assert_eq!(timeout_for_rtt(Duration::from_millis(100), Duration::from_secs(1)), Duration::from_millis(450));
+    }
+}
@@ -0,0 +1,186 @@
+//! Resume files for `--resume`.
+//!
+//! Every finished port is appended as one line, so an interrupted scan can
+//! be restarted without repeating the work already done:
+//!
+//! ```text
+//! # port_sniffer resume file: ip port protocol status
+//! 192.168.0.1 21 tcp closed
+//! 192.168.0.1 22 tcp open
+//! 192.168.0.1 23 tcp timeout
+//! ```
+//!
+//! Lines are buffered and written every `FLUSH_EVERY` results and when a
+//! scan ends, so a crash loses at most that many results, which are then
+//! simply scanned again. A port listed more than once (e.g. a host repeated
+//! with `--allow-duplicates`, or a file appended to by several runs) counts
+//! once, with the status of its last line.
+
+use std::collections::{BTreeSet, HashMap}; // Finished ports by status and host
+use std::fs::{File, OpenOptions}; // The resume file
+use std::io::{self, BufRead, BufReader, BufWriter, Write}; // Line-based reading and buffered appends
+use std::net::IpAddr; // Represents an IP address
+use std::path::Path; // Filesystem paths
+use std::sync::Mutex; // Shared writer across attempts
+
+/// First line of every resume file
+const HEADER: &str = "# port_sniffer resume file: ip port protocol status";
+/// Results buffered before they are written out
+const FLUSH_EVERY: usize = 256;
+
+/// Ports already finished on one host, ascending; each port is in exactly
+/// one of the sets
+#[derive(Default)]
+pub struct Completed {
+    /// Ports that accepted a connection
+    pub open: BTreeSet<u16>,
+    /// Ports that refused the connection
+    pub closed: BTreeSet<u16>,
+    /// Ports whose attempt timed out
+    pub timed_out: BTreeSet<u16>,
+}
+
+impl Completed {
+    /// Every finished port, whatever its result
+    pub fn ports(&self) -> impl Iterator<Item = u16> + '_ {
+        self.open.iter().chain(&self.closed).chain(&self.timed_out).copied()
+    }
+
+    /// Records `port` with the status `status`, replacing an earlier one
+    fn insert(&mut self, port: u16, status: &str) -> Result<(), ()> {
+        if !matches!(status, "open" | "closed" | "timeout") {
+            return Err(());
+        }
+        self.open.remove(&port);
+        self.closed.remove(&port);
+        self.timed_out.remove(&port);
+        match status {
+            "open" => self.open.insert(port),
+            "closed" => self.closed.insert(port),
+            _ => self.timed_out.insert(port),
+        };
+        Ok(())
+    }
+}
+
+/// Reads the finished ports for `protocol` from the resume file at `path`.
+///
+/// A missing file means nothing was finished yet. Lines for other
+/// protocols are ignored; malformed lines are an error naming the line.
+pub fn load(path: &Path, protocol: &str) -> io::Result<HashMap<IpAddr, Completed>> {
+    let mut completed: HashMap<IpAddr, Completed> = HashMap::new();
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(completed),
+        Err(e) => return Err(e),
+    };
+
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("line {}: `{line}` is not `ip port protocol status`", number + 1));
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [ip, port, line_protocol, status] = fields[..] else {
+            return Err(invalid());
+        };
+        let ip: IpAddr = ip.parse().map_err(|_| invalid())?;
+        let port: u16 = port.parse().map_err(|_| invalid())?;
+        if line_protocol != protocol {
+            continue;
+        }
+        completed.entry(ip).or_default().insert(port, status).map_err(|()| invalid())?;
+    }
+    Ok(completed)
+}
+
+/// Appends finished ports to a resume file.
+pub struct Checkpoint {
+    /// Buffered appender and the number of unwritten results
+    writer: Mutex<(BufWriter<File>, usize)>,
+    /// Protocol name written on every line
+    protocol: &'static str,
+}
+
+impl Checkpoint {
+    /// Opens `path` for appending, creating it with a header if needed.
+    pub fn open(path: &Path, protocol: &'static str) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut writer = BufWriter::new(file);
+        if writer.get_ref().metadata()?.len() == 0 {
+            writeln!(writer, "{HEADER}")?;
+            writer.flush()?;
+        }
+        Ok(Self { writer: Mutex::new((writer, 0)), protocol })
+    }
+
+    /// Records the result of one port. Write errors are ignored here and
+    /// only reported by `flush`, so they never interrupt the scan.
+    pub(crate) fn record(&self, addr: IpAddr, port: u16, status: &str) {
+        let mut guard = self.writer.lock().expect("checkpoint lock poisoned");
+        let (writer, pending) = &mut *guard;
+        let _ = writeln!(writer, "{addr} {port} {} {status}", self.protocol);
+        *pending += 1;
+        if *pending >= FLUSH_EVERY {
+            *pending = 0;
+            let _ = writer.flush();
+        }
+    }
+
+    /// Writes out all buffered results.
+    pub fn flush(&self) -> io::Result<()> {
+        let mut guard = self.writer.lock().expect("checkpoint lock poisoned");
+        guard.1 = 0;
+        guard.0.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const HOST: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1));
+
+    /// Loads `lines` from a resume file written for this test
+    fn load_lines(name: &str, lines: &str) -> io::Result<HashMap<IpAddr, Completed>> {
+        let path = std::env::temp_dir().join(format!("port_sniffer_checkpoint_{name}_{}", std::process::id()));
+        std::fs::write(&path, format!("{HEADER}\n{lines}")).expect("temporary file is writable");
+        let loaded = load(&path, "tcp");
+        let _ = std::fs::remove_file(&path);
+        loaded
+    }
+
+    #[test]
+    fn repeated_and_conflicting_lines_count_once_with_the_last_status() {
+        let completed = load_lines(
+            "duplicates",
+            "192.168.0.1 22 tcp open\n\
+             192.168.0.1 22 tcp open\n\
+             192.168.0.1 80 tcp timeout\n\
+             192.168.0.1 80 tcp open\n\
+             192.168.0.1 443 tcp open\n\
+             192.168.0.1 443 tcp closed\n\
+             192.168.0.1 443 udp open\n",
+        )
+        .expect("valid resume file");
+        let host = &completed[&HOST];
+        assert_eq!(host.open, BTreeSet::from([22, 80]));
+        assert_eq!(host.closed, BTreeSet::from([443]));
+        assert!(host.timed_out.is_empty());
+        assert_eq!(host.ports().count(), 3);
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected_with_their_number() {
+        let error = load_lines("malformed", "192.168.0.1 22 tcp open\n192.168.0.1 23 tcp maybe\n").err().expect("rejected");
+        assert!(error.to_string().starts_with("line 3:"), "{error}");
+    }
+
+    #[test]
+    fn a_missing_file_has_nothing_finished() {
+        let path = std::env::temp_dir().join(format!("port_sniffer_checkpoint_missing_{}", std::process::id()));
+        assert!(load(&path, "tcp").expect("missing file is fine").is_empty());
+    }
+}
@@ -0,0 +1,303 @@
+//! Target specifications: single addresses, CIDR ranges and host names.
+//!
+//! `--ip` accepts a plain address, a network such as `192.168.1.0/24`,
+//! which is expanded into its host addresses, or a DNS name, which is
+//! resolved. Both happen before the scan starts. IPv6 addresses may carry
+//! a zone (`fe80::1%eth0`) naming the interface to scan them on.
+//! `--target-file` reads the same values from a file, one per line, and
+//! `--target` takes a single address with a port. `--family` limits the
+//! targets to IPv4 or IPv6.
+
+use clap::ValueEnum; // `--family` values
+use std::fmt; // Displaying a target as it was given
+use std::io::Read; // Reading target lists from stdin
+use std::path::Path; // Target list files
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr}; // Address arithmetic
+use tokio::net::lookup_host; // Resolving host names
+
+/// `--target-file` value that reads the list from stdin
+pub const STDIN: &str = "-";
+
+/// Largest range accepted, as the number of host bits (/16 for IPv4, /112
+/// for IPv6), so a typo cannot expand into millions of addresses
+const MAX_HOST_BITS: u8 = 16;
+
+/// Address family of the targets to scan
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Family {
+    /// IPv4 and IPv6 (default)
+    Any,
+    /// IPv4 only
+    Ipv4,
+    /// IPv6 only
+    Ipv6,
+}
+
+impl Family {
+    /// Name as accepted by `--family`
+    pub fn name(self) -> &'static str {
+        match self {
+            Family::Any => "any",
+            Family::Ipv4 => "ipv4",
+            Family::Ipv6 => "ipv6",
+        }
+    }
+
+    /// Whether `ip` belongs to this family
+    pub fn includes(self, ip: IpAddr) -> bool {
+        match self {
+            Family::Any => true,
+            Family::Ipv4 => ip.is_ipv4(),
+            Family::Ipv6 => ip.is_ipv6(),
+        }
+    }
+}
+
+/// `IPv4` or `IPv6`, for labeling results
+pub fn family_label(ip: IpAddr) -> &'static str {
+    if ip.is_ipv4() { "IPv4" } else { "IPv6" }
+}
+
+/// One `--ip` value
+#[derive(Clone)]
+pub enum Target {
+    /// A single address
+    Addr(IpAddr),
+    /// An IPv6 address with a zone, as written, and its interface index
+    Scoped(Ipv6Addr, String, u32),
+    /// A network address with its prefix length
+    Network(IpAddr, u8),
+    /// A DNS name, resolved before scanning
+    Host(String),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Addr(addr) => write!(f, "{addr}"),
+            Target::Scoped(addr, zone, _) => write!(f, "{addr}%{zone}"),
+            Target::Network(addr, prefix) => write!(f, "{addr}/{prefix}"),
+            Target::Host(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+/// One `--target` value: a single address and the port to check on it
+#[derive(Clone)]
+pub struct Endpoint {
+    /// The address, `Target::Addr` or `Target::Scoped`
+    pub target: Target,
+    /// Port to connect to
+    pub port: u16,
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.target {
+            Target::Addr(IpAddr::V4(_)) => write!(f, "{}:{}", self.target, self.port),
+            _ => write!(f, "[{}]:{}", self.target, self.port),
+        }
+    }
+}
+
+/// Parses `10.0.0.1:22`, `[2001:db8::1]:443` or `[fe80::1%eth0]:22`.
+///
+/// Only literal addresses are accepted: the point is to check one socket
+/// address exactly, without ranges or name resolution. IPv6 addresses must
+/// be bracketed so the port cannot be mistaken for part of the address.
+pub fn parse_endpoint(value: &str) -> Result<Endpoint, String> {
+    let (addr, port) = match value.strip_prefix('[') {
+        Some(rest) => rest.split_once("]:").ok_or_else(|| format!("`{value}` must be [ADDR]:PORT"))?,
+        None => {
+            let (addr, port) = value.rsplit_once(':').ok_or_else(|| format!("`{value}` must be ADDR:PORT"))?;
+            if addr.contains(':') {
+                return Err(format!("`{value}`: IPv6 addresses must be written [ADDR]:PORT"));
+            }
+            (addr, port)
+        }
+    };
+    let port: u16 = port.parse().ok().filter(|&p| p != 0).ok_or_else(|| format!("`{port}` is not a valid port"))?;
+    match parse(addr)? {
+        target @ (Target::Addr(_) | Target::Scoped(..)) => Ok(Endpoint { target, port }),
+        _ => Err(format!("`{addr}` is not an IP address")),
+    }
+}
+
+/// Parses `10.0.0.1`, `10.0.0.0/24`, `fd00::/120`, `fe80::1%eth0` or
+/// `example.com`.
+///
+/// Host bits set in a network (`10.0.0.7/24`) are cleared, so it denotes the
+/// same range as `10.0.0.0/24`. Anything else made of letters, digits, `-`,
+/// `_` and `.` is taken as a host name; whether it exists is only known once
+/// it is resolved.
+pub fn parse(value: &str) -> Result<Target, String> {
+    let Some((addr, prefix)) = value.split_once('/') else {
+        if let Some((addr, zone)) = value.split_once('%') {
+            let addr: Ipv6Addr = addr.parse().map_err(|_| format!("`{addr}` is not an IPv6 address; zones only apply to IPv6"))?;
+            return Ok(Target::Scoped(addr, zone.to_string(), zone_index(zone)?));
+        }
+        if let Ok(addr) = value.parse() {
+            return Ok(Target::Addr(addr));
+        }
+        if is_host_name(value) {
+            return Ok(Target::Host(value.to_string()));
+        }
+        return Err(format!("`{value}` is not an IP address, CIDR range or host name"));
+    };
+
+    let addr: IpAddr = addr.parse().map_err(|_| format!("`{addr}` is not an IP address"))?;
+    let bits = address_bits(addr);
+    let prefix: u8 = prefix
+        .parse()
+        .ok()
+        .filter(|&p| p <= bits)
+        .ok_or_else(|| format!("`{prefix}` is not a valid prefix length (0-{bits})"))?;
+    if bits - prefix > MAX_HOST_BITS {
+        return Err(format!(
+            "`{value}` is too large; ranges are limited to /{} for {}",
+            bits - MAX_HOST_BITS,
+            if addr.is_ipv4() { "IPv4" } else { "IPv6" }
+        ));
+    }
+
+    Ok(Target::Network(from_bits(addr, to_bits(addr) & mask(bits, prefix)), prefix))
+}
+
+impl Target {
+    /// Host addresses covered by the target, in ascending order.
+    ///
+    /// For IPv4 the network and broadcast addresses are skipped unless
+    /// `include_network` is set (a /24 yields 254 hosts); /31 and /32 have no
+    /// such addresses. For IPv6 only the all-zeros subnet-router address is
+    /// skipped, as there is no broadcast.
+    ///
+    /// A host name has no addresses until it is resolved (see `resolve`).
+    pub fn hosts(&self, include_network: bool) -> Vec<IpAddr> {
+        let (network, prefix) = match *self {
+            Target::Addr(addr) => return vec![addr],
+            Target::Scoped(addr, _, _) => return vec![IpAddr::V6(addr)],
+            Target::Network(network, prefix) => (network, prefix),
+            Target::Host(_) => return Vec::new(),
+        };
+
+        let host_bits = address_bits(network) - prefix;
+        let first = to_bits(network);
+        let last = first + ((1u128 << host_bits) - 1);
+        let (first, last) = if include_network || host_bits <= 1 {
+            (first, last)
+        } else if network.is_ipv4() {
+            (first + 1, last - 1)
+        } else {
+            (first + 1, last)
+        };
+        (first..=last).map(|bits| from_bits(network, bits)).collect()
+    }
+
+    /// Interface index to scan the target's addresses on, 0 for none
+    pub fn scope_id(&self) -> u32 {
+        match *self {
+            Target::Scoped(_, _, scope_id) => scope_id,
+            _ => 0,
+        }
+    }
+}
+
+/// Interface index for a zone: numeric zones are taken as the index itself,
+/// names are looked up on platforms that have them (e.g. `eth0`, `en0`)
+fn zone_index(zone: &str) -> Result<u32, String> {
+    if let Ok(index) = zone.parse() {
+        return Ok(index);
+    }
+    #[cfg(unix)]
+    {
+        let name = std::ffi::CString::new(zone).map_err(|_| format!("`{zone}` is not a valid zone"))?;
+        // SAFETY: `name` is a valid NUL-terminated string for the duration of the call
+        match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+            0 => Err(format!("no network interface named `{zone}`")),
+            index => Ok(index),
+        }
+    }
+    #[cfg(not(unix))]
+    Err(format!("zone `{zone}` must be a numeric interface index on this platform"))
+}
+
+/// Resolves a host name to its addresses, in the order the system resolver
+/// returned them and without duplicates.
+pub async fn resolve(name: &str) -> Result<Vec<IpAddr>, String> {
+    let resolved = lookup_host((name, 0)).await.map_err(|e| format!("cannot resolve `{name}`: {e}"))?;
+    let mut addrs: Vec<IpAddr> = Vec::new();
+    for addr in resolved {
+        if !addrs.contains(&addr.ip()) {
+            addrs.push(addr.ip());
+        }
+    }
+    if addrs.is_empty() {
+        return Err(format!("`{name}` has no addresses"));
+    }
+    Ok(addrs)
+}
+
+/// Whether `value` looks like a DNS name: dot-separated labels of letters,
+/// digits, `-` and `_`, with an optional trailing dot
+fn is_host_name(value: &str) -> bool {
+    let name = value.strip_suffix('.').unwrap_or(value);
+    !name.is_empty()
+        && name.len() <= 253
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        })
+}
+
+/// Number of bits in the address family of `addr`
+fn address_bits(addr: IpAddr) -> u8 {
+    if addr.is_ipv4() { 32 } else { 128 }
+}
+
+/// Network mask with the top `prefix` of `bits` bits set
+fn mask(bits: u8, prefix: u8) -> u128 {
+    let all = if bits == 128 { u128::MAX } else { (1u128 << bits) - 1 };
+    all & !(all.checked_shr(prefix.into()).unwrap_or(0))
+}
+
+/// Address as an integer
+fn to_bits(addr: IpAddr) -> u128 {
+    match addr {
+        IpAddr::V4(v4) => u32::from(v4).into(),
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
+
+/// Integer back to an address of the same family as `like`
+fn from_bits(like: IpAddr, bits: u128) -> IpAddr {
+    match like {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from(bits as u32)),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::from(bits)),
+    }
+}
+
+/// Reads a target list from `path` (stdin for `-`): one target per line in
+/// the syntax of `parse`, with blank lines and `#` comments ignored. An
+/// entry that does not parse is an error naming its line.
+pub fn read_list(path: &Path) -> Result<Vec<Target>, String> {
+    let (text, name) = if path.as_os_str() == STDIN {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text).map_err(|e| format!("cannot read targets from stdin: {e}"))?;
+        (text, String::from("stdin"))
+    } else {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+        (text, path.display().to_string())
+    };
+
+    let mut targets = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let entry = line.split_once('#').map_or(line, |(entry, _)| entry).trim();
+        if entry.is_empty() {
+            continue;
+        }
+        targets.push(parse(entry).map_err(|e| format!("{name} line {}: {e}", i + 1))?);
+    }
+    Ok(targets)
+}
@@ -0,0 +1,84 @@
+//! ANSI colors for the text report (`--color`).
+//!
+//! Only the human-readable text format is colored; every other format is
+//! meant for programs and stays plain.
+
+use clap::ValueEnum; // `--color` values
+use std::io::IsTerminal; // Auto-detecting a terminal
+
+/// When to color the text report
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Color when stdout is a terminal and `NO_COLOR` is not set (default)
+    Auto,
+    /// Always color, even into files and pipes
+    Always,
+    /// Never color
+    Never,
+}
+
+impl ColorChoice {
+    /// Name as accepted by `--color`
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorChoice::Auto => "auto",
+            ColorChoice::Always => "always",
+            ColorChoice::Never => "never",
+        }
+    }
+
+    /// Whether to color a report going to stdout (`to_stdout`) or a file.
+    ///
+    /// `NO_COLOR` (https://no-color.org) only counts when set to a
+    /// non-empty value.
+    pub fn enabled(self, to_stdout: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                to_stdout
+                    && std::io::stdout().is_terminal()
+                    && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+        }
+    }
+}
+
+/// Wraps report text in color codes, or passes it through when disabled.
+#[derive(Clone, Copy)]
+pub struct Palette {
+    /// Whether escape codes are written at all
+    enabled: bool,
+}
+
+impl Palette {
+    /// A palette that colors only when `enabled`
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+
+    /// Open ports
+    pub fn open(self, text: &str) -> String {
+        self.paint("32", text)
+    }
+
+    /// Service names
+    pub fn service(self, text: &str) -> String {
+        self.paint("36", text)
+    }
+
+    /// Addresses no connection got through to
+    pub fn unreachable(self, text: &str) -> String {
+        self.paint("31", text)
+    }
+
+    /// Summary counts and other secondary lines
+    pub fn dim(self, text: &str) -> String {
+        self.paint("2", text)
+    }
+
+    /// `text` between the SGR code `code` and a reset
+    fn paint(self, code: &str, text: &str) -> String {
+        if self.enabled { format!("\x1b[{code}m{text}\x1b[0m") } else { text.to_string() }
+    }
+}
@@ -0,0 +1,99 @@
+//! Saved defaults in a TOML configuration file (`--config <FILE>`).
+//!
+//! Without `--config`, `port_sniffer_cli/config.toml` in the user's
+//! configuration directory is read if it exists. Every setting is optional;
+//! a scan plan overrides the file, and a flag given explicitly on the command
+//! line overrides both.
+//!
+//! ```toml
+//! concurrency = 200
+//! timeout = "1500ms"
+//! format = "json"
+//! ports = "22,80,443,8000-8100"
+//! ```
+//!
+//! Values are checked like the matching flags, and unknown keys are rejected
+//! so a typo does not go unnoticed.
+
+use crate::duration; // `timeout` values
+use crate::ranges; // `ports` values
+use crate::report::Format; // Output format
+use serde::Deserialize; // File deserialization
+use std::path::{Path, PathBuf}; // Configuration file paths
+use std::time::Duration; // Parsed timeout
+
+/// Directory (under the user's configuration directory) and file name
+/// searched when `--config` is not given
+const DEFAULT_FILE: &str = "port_sniffer_cli/config.toml";
+
+/// Settings as written in the file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    /// Concurrent connection attempts
+    concurrency: Option<usize>,
+    /// Connect timeout, e.g. `"1500ms"` (same syntax as `--timeout`)
+    timeout: Option<String>,
+    /// Report format (same names as `--format`)
+    format: Option<Format>,
+    /// Ports to scan (same syntax as `--ports`)
+    ports: Option<String>,
+}
+
+/// Validated defaults from a configuration file
+#[derive(Default)]
+pub struct Config {
+    /// Concurrent connection attempts
+    pub concurrency: Option<usize>,
+    /// Connect timeout
+    pub timeout: Option<Duration>,
+    /// Report format
+    pub format: Option<Format>,
+    /// Sorted, deduplicated ports to scan
+    pub ports: Option<Vec<u16>>,
+}
+
+/// The file read when `--config` is not given: `$XDG_CONFIG_HOME` or
+/// `~/.config` on Unix, `%APPDATA%` on Windows.
+pub fn default_path() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.map(|dir| dir.join(DEFAULT_FILE))
+}
+
+/// Reads, parses and validates the configuration file at `path`.
+///
+/// Errors are single-line messages naming the file and the bad key.
+pub fn load(path: &Path, max_concurrency: usize, max_timeout: Duration) -> Result<Config, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+    let raw: RawConfig = toml::from_str(&text).map_err(|e| {
+        let line = e.span().map_or(1, |span| text[..span.start].matches('\n').count() + 1);
+        format!("{} line {line}: {}", path.display(), e.message())
+    })?;
+    let invalid = |key: &str, msg: String| format!("{}: at `{key}`: {msg}", path.display());
+
+    if let Some(concurrency) = raw.concurrency
+        && !(1..=max_concurrency).contains(&concurrency)
+    {
+        return Err(invalid("concurrency", format!("must be between 1 and {max_concurrency}")));
+    }
+    let timeout = match raw.timeout {
+        Some(value) => {
+            let timeout = duration::parse_non_zero(&value).map_err(|e| invalid("timeout", e))?;
+            if timeout > max_timeout {
+                return Err(invalid("timeout", format!("must be at most {}ms", max_timeout.as_millis())));
+            }
+            Some(timeout)
+        }
+        None => None,
+    };
+    let ports = raw.ports.map(|spec| ranges::parse_spec(&spec)).transpose().map_err(|e| invalid("ports", e))?;
+
+    Ok(Config { concurrency: raw.concurrency, timeout, format: raw.format, ports })
+}
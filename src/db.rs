@@ -0,0 +1,163 @@
+//! SQLite export of scan results.
+//!
+//! Each run inserts one row into `scans`, one row per scanned host into
+//! `hosts` and one row per attempted port into `ports`, with its status
+//! (`open`, `closed` or `timeout`), so results from many runs can be queried
+//! together with plain SQL. `--diff` compares a run with the previous one
+//! recorded for each host.
+
+use rusqlite::{Connection, OptionalExtension, params}; // SQLite bindings
+use std::net::IpAddr; // Represents an IP address
+use std::path::Path; // Filesystem paths
+use std::time::{SystemTime, UNIX_EPOCH}; // Wall-clock timestamps
+
+/// Schema created on first use; existing tables are left untouched.
+///
+/// Timestamps are stored as Unix epoch seconds, so they can be formatted
+/// with `datetime(column, 'unixepoch')`.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS scans (
+        id          INTEGER PRIMARY KEY,
+        started_at  INTEGER NOT NULL,
+        finished_at INTEGER NOT NULL,
+        start_port  INTEGER NOT NULL,
+        end_port    INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS hosts (
+        id      INTEGER PRIMARY KEY,
+        scan_id INTEGER NOT NULL REFERENCES scans(id),
+        ip      TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS ports (
+        id          INTEGER PRIMARY KEY,
+        host_id     INTEGER NOT NULL REFERENCES hosts(id),
+        port        INTEGER NOT NULL,
+        status      TEXT NOT NULL,
+        recorded_at INTEGER NOT NULL
+    );
+";
+
+/// Opens (or creates) the database at `path` and ensures the schema exists.
+///
+/// Called before the scan starts so a bad path fails early.
+pub fn open(path: &Path) -> rusqlite::Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.execute_batch(SCHEMA)?;
+    Ok(conn)
+}
+
+/// Result of one attempted port, as stored in `ports.status`
+#[derive(Clone, Copy)]
+pub enum Status {
+    /// Accepted the connection
+    Open,
+    /// Refused the connection
+    Closed,
+    /// No answer before the timeout
+    Timeout,
+}
+
+impl Status {
+    /// Value of the `status` column
+    fn name(self) -> &'static str {
+        match self {
+            Status::Open => "open",
+            Status::Closed => "closed",
+            Status::Timeout => "timeout",
+        }
+    }
+}
+
+/// One scanned host and the status of every port attempted on it
+pub struct HostRecord {
+    /// Address of the host
+    pub ip: IpAddr,
+    /// Attempted ports and their results
+    pub ports: Vec<(u16, Status)>,
+}
+
+/// An earlier scan of one host, as recorded
+pub struct PreviousScan {
+    /// Row id in `scans`
+    pub id: i64,
+    /// Start time, in Unix epoch seconds
+    pub started_at: i64,
+    /// Ports with a recorded result on the host, in ascending order
+    pub attempted: Vec<u16>,
+    /// Ports found open on the host, in ascending order
+    pub open: Vec<u16>,
+}
+
+/// Records one finished scan together with every attempted port of each
+/// host and returns its id in `scans`.
+///
+/// Everything is written in a single transaction, so a failed export never
+/// leaves a half-recorded scan behind.
+pub fn record_scan(
+    conn: &mut Connection,
+    started_at: SystemTime,
+    start_port: u16,
+    end_port: u16,
+    hosts: &[HostRecord],
+) -> rusqlite::Result<i64> {
+    let started = epoch_secs(started_at);
+    let finished = epoch_secs(SystemTime::now());
+
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT INTO scans (started_at, finished_at, start_port, end_port) VALUES (?1, ?2, ?3, ?4)",
+        params![started, finished, start_port, end_port],
+    )?;
+    let scan_id = tx.last_insert_rowid();
+
+    for host in hosts {
+        tx.execute(
+            "INSERT INTO hosts (scan_id, ip) VALUES (?1, ?2)",
+            params![scan_id, host.ip.to_string()],
+        )?;
+        let host_id = tx.last_insert_rowid();
+
+        let mut stmt = tx.prepare_cached(
+            "INSERT INTO ports (host_id, port, status, recorded_at) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for (port, status) in &host.ports {
+            stmt.execute(params![host_id, port, status.name(), finished])?;
+        }
+    }
+
+    tx.commit()?;
+    Ok(scan_id)
+}
+
+/// The most recent scan of `ip` recorded before scan `before`, if any.
+///
+/// Databases written before every attempt was recorded only hold the open
+/// ports, so for those scans `attempted` is the same as `open`.
+pub fn previous_scan(conn: &Connection, ip: IpAddr, before: i64) -> rusqlite::Result<Option<PreviousScan>> {
+    let row = conn
+        .query_row(
+            "SELECT hosts.id, scans.id, scans.started_at
+             FROM hosts JOIN scans ON scans.id = hosts.scan_id
+             WHERE hosts.ip = ?1 AND scans.id < ?2
+             ORDER BY scans.id DESC LIMIT 1",
+            params![ip.to_string(), before],
+            |row| Ok((row.get::<_, i64>(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+    let Some((host_id, id, started_at)) = row else {
+        return Ok(None);
+    };
+
+    let mut stmt = conn.prepare("SELECT port, status FROM ports WHERE host_id = ?1 ORDER BY port")?;
+    let rows = stmt
+        .query_map(params![host_id], |row| Ok((row.get::<_, u16>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let attempted = rows.iter().map(|(port, _)| *port).collect();
+    let open = rows.iter().filter(|(_, status)| status == "open").map(|(port, _)| *port).collect();
+    Ok(Some(PreviousScan { id, started_at, attempted, open }))
+}
+
+/// Converts a wall-clock time to whole seconds since the Unix epoch.
+fn epoch_secs(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
@@ -0,0 +1,41 @@
+//! Parsing of human-friendly duration flags such as `500ms`, `2s` or `1m`.
+
+use std::time::Duration; // Parsed value
+
+/// Supported unit suffixes and their length in milliseconds
+const UNITS: &[(&str, f64)] = &[("ms", 1.0), ("s", 1_000.0), ("m", 60_000.0), ("h", 3_600_000.0)];
+
+/// Parses a duration flag value.
+///
+/// Accepts a number followed by `ms`, `s`, `m` or `h` (e.g. `500ms`, `1.5s`).
+/// A bare integer is taken as milliseconds, matching the flags' original
+/// unit.
+pub fn parse(x: &str) -> Result<Duration, String> {
+    let x = x.trim();
+    let split = x.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(x.len());
+    let (number, unit) = x.split_at(split);
+
+    if unit.is_empty() {
+        let millis: u64 = number.parse().map_err(|_| format!("`{x}` is not a valid duration"))?;
+        return Ok(Duration::from_millis(millis));
+    }
+
+    let scale = UNITS
+        .iter()
+        .find(|(suffix, _)| *suffix == unit)
+        .map(|(_, scale)| *scale)
+        .ok_or_else(|| format!("`{x}` has an unknown unit `{unit}` (use ms, s, m or h)"))?;
+    let value: f64 = number.parse().map_err(|_| format!("`{x}` is not a valid duration"))?;
+
+    Duration::try_from_secs_f64(value * scale / 1_000.0).map_err(|_| format!("`{x}` is out of range"))
+}
+
+/// Like `parse`, but rejects a zero duration.
+pub fn parse_non_zero(x: &str) -> Result<Duration, String> {
+    let duration = parse(x)?;
+    if duration.is_zero() {
+        Err(String::from("Duration must be greater than zero"))
+    } else {
+        Ok(duration)
+    }
+}
@@ -0,0 +1,109 @@
+//! Live result streaming over a Unix domain socket.
+//!
+//! Each open port is written as one JSON object per line (JSONL) as soon as
+//! it is found:
+//!
+//! ```text
+//! {"ip":"192.168.0.1","port":22,"status":"open"}
+//! ```
+//!
+//! The socket is a convenience for local collectors, never a requirement: if
+//! it cannot be reached, or a write fails mid-scan, a warning is printed and
+//! the scan carries on without it.
+//!
+//! Lines are buffered and flushed after every `flush_every` results, and at
+//! least every `flush_interval` by the caller's periodic `flush()`. Smaller
+//! values get results to the collector sooner at the cost of more writes.
+
+use std::net::IpAddr; // Represents an IP address
+use std::path::Path; // Filesystem paths
+use std::time::Duration; // Flush interval
+
+#[cfg(unix)]
+use crate::json_line; // Same line format as `--format jsonl`
+#[cfg(unix)]
+use tokio::io::{AsyncWriteExt, BufWriter}; // Buffered async writes on the socket
+#[cfg(unix)]
+use tokio::net::UnixStream; // Unix domain socket client
+
+/// Streams open ports to a connected Unix domain socket.
+pub struct SocketEmitter {
+    /// `None` once the socket is unreachable or has failed
+    #[cfg(unix)]
+    stream: Option<BufWriter<UnixStream>>,
+    /// Results written since the last flush
+    pending: usize,
+    /// Flush after this many results
+    flush_every: usize,
+    /// Longest a result may sit in the buffer
+    flush_interval: Duration,
+}
+
+impl SocketEmitter {
+    /// Connects to the socket at `path`, warning (and emitting nothing) if
+    /// no collector is listening there.
+    pub async fn connect(path: &Path, flush_every: usize, flush_interval: Duration) -> Self {
+        #[cfg(unix)]
+        {
+            let stream = match UnixStream::connect(path).await {
+                Ok(stream) => Some(BufWriter::new(stream)),
+                Err(e) => {
+                    eprintln!("Warning: cannot connect to {}: {e}; results will not be streamed", path.display());
+                    None
+                }
+            };
+            Self { stream, pending: 0, flush_every, flush_interval }
+        }
+
+        #[cfg(not(unix))]
+        {
+            eprintln!("Warning: --emit-socket {} ignored: Unix domain sockets are not supported on this platform", path.display());
+            Self { pending: 0, flush_every, flush_interval }
+        }
+    }
+
+    /// How often the caller should invoke `flush()` while results trickle in
+    pub fn flush_interval(&self) -> Duration {
+        self.flush_interval
+    }
+
+    /// Buffers one result line, flushing once `flush_every` lines are
+    /// pending; on failure the socket is dropped for the rest of the scan.
+    pub async fn emit(&mut self, ip: IpAddr, port: u16) {
+        #[cfg(unix)]
+        if let Some(stream) = self.stream.as_mut() {
+            let line = format!("{}\n", json_line(ip, port));
+            if let Err(e) = stream.write_all(line.as_bytes()).await {
+                self.fail(e);
+                return;
+            }
+            self.pending += 1;
+            if self.pending >= self.flush_every {
+                self.flush().await;
+            }
+        }
+
+        #[cfg(not(unix))]
+        let _ = (ip, port);
+    }
+
+    /// Sends any buffered lines to the collector.
+    pub async fn flush(&mut self) {
+        #[cfg(unix)]
+        if self.pending > 0
+            && let Some(stream) = self.stream.as_mut()
+        {
+            match stream.flush().await {
+                Ok(()) => self.pending = 0,
+                Err(e) => self.fail(e),
+            }
+        }
+    }
+
+    /// Drops the socket after a write error.
+    #[cfg(unix)]
+    fn fail(&mut self, e: std::io::Error) {
+        eprintln!("Warning: result socket write failed: {e}; no further results will be streamed");
+        self.stream = None;
+    }
+}
@@ -0,0 +1,57 @@
+//! Open-file limit check for high `--concurrency`.
+//!
+//! Every connection attempt in flight holds a socket, so a concurrency above
+//! the process's open-file limit makes attempts fail with "Too many open
+//! files", which the scan would report as closed ports. Before scanning, the
+//! soft limit is raised toward the hard limit if needed; if that is still
+//! not enough, the concurrency is lowered to fit.
+
+#[cfg(unix)]
+use log::debug; // Reporting a raised limit
+
+/// Descriptors kept free for stdio, reports, databases and sockets outside
+/// the scan pool
+pub const FD_RESERVE: u64 = 64;
+
+/// Makes room for `concurrency` sockets and returns the concurrency that
+/// fits, which is `concurrency` itself unless the hard limit is too low.
+/// Any change is explained on stderr.
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)] // `rlim_t` is not `u64` on every Unix
+pub fn fit_concurrency(concurrency: usize) -> usize {
+    let needed = concurrency as u64 + FD_RESERVE;
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    // SAFETY: getrlimit only writes to the struct we pass
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return concurrency;
+    }
+    let soft = limit.rlim_cur as u64;
+    if limit.rlim_cur == libc::RLIM_INFINITY || soft >= needed {
+        return concurrency;
+    }
+
+    // Raising the soft limit up to the hard one needs no privileges
+    let hard = if limit.rlim_max == libc::RLIM_INFINITY { u64::MAX } else { limit.rlim_max as u64 };
+    let raised = libc::rlimit { rlim_cur: needed.min(hard) as libc::rlim_t, rlim_max: limit.rlim_max };
+    // SAFETY: setrlimit only reads the struct we pass
+    let available = if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &raised) } == 0 { raised.rlim_cur as u64 } else { soft };
+    if available > soft {
+        debug!("raised the open file limit from {soft} to {available}");
+    }
+    if available >= needed {
+        return concurrency;
+    }
+
+    let fitted = available.saturating_sub(FD_RESERVE).max(1) as usize;
+    eprintln!(
+        "Warning: the open file limit ({available}) is too low for --concurrency {concurrency}; using {fitted} \
+         (raise it with `ulimit -n {needed}`)"
+    );
+    fitted
+}
+
+/// Open-file limits are a Unix notion; elsewhere the concurrency is used as is.
+#[cfg(not(unix))]
+pub fn fit_concurrency(concurrency: usize) -> usize {
+    concurrency
+}
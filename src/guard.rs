@@ -0,0 +1,118 @@
+//! Confirmation guards against accidentally huge scans and public targets.
+//!
+//! Scans above a configurable number of connection attempts ask for
+//! confirmation on an interactive terminal, and must be acknowledged with
+//! `--yes` when stdin is not a terminal. Targets outside private and local
+//! address space are refused unless `--i-know-what-im-doing` is given.
+
+use port_sniffer::ScanConfig; // Pacing used for the estimate
+use std::io::{BufRead, IsTerminal, Write}; // Prompting on the terminal
+use std::net::{IpAddr, Ipv4Addr}; // Classifying targets
+use std::time::Duration; // Estimated run time
+
+/// Upper bound on how long `attempts` connection attempts can take with
+/// `config`: every attempt hangs for the longest host timeout and is retried as
+/// often as allowed, plus all chunk pauses. A `rate` limit slower than that
+/// sets the pace instead.
+pub fn estimate_worst_case(attempts: u64, config: &ScanConfig) -> Duration {
+    let concurrency = config.concurrency.max(1) as u64;
+    let chunk = config.chunk_size.max(1) as u64;
+    let retries = config.retries.min(u32::MAX as usize) as u32;
+    let connect_timeout = config.hosts.iter().map(|host| host.connect_timeout).max().unwrap_or_default();
+    let per_attempt = config.simulate_latency.unwrap_or(connect_timeout).saturating_mul(retries.saturating_add(1))
+        + config.backoff.total(config.retries);
+
+    // Each batch runs in rounds of `concurrency` attempts
+    let full_batches = attempts / chunk;
+    let remainder = attempts % chunk;
+    let rounds = full_batches * chunk.div_ceil(concurrency) + remainder.div_ceil(concurrency);
+    let pauses = attempts.div_ceil(chunk).saturating_sub(1);
+
+    let attempting = per_attempt.saturating_mul(rounds.min(u32::MAX as u64) as u32);
+    let launching = config.rate.map_or(Duration::ZERO, |rate| Duration::from_secs_f64(attempts as f64 / f64::from(rate.max(1))));
+    attempting.max(launching) + config.chunk_pause.saturating_mul(pauses.min(u32::MAX as u64) as u32)
+}
+
+/// Formats an estimate coarsely, e.g. `2h 5m`, `3m 10s` or `45s`.
+pub fn format_estimate(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m {s}s"),
+        (h, m, _) => format!("{h}h {m}m"),
+    }
+}
+
+/// Returns whether a scan of `attempts` connection attempts may go ahead.
+///
+/// Scans at or below `threshold`, or acknowledged with `--yes`, always
+/// proceed. Larger ones prompt on a terminal and are refused otherwise.
+pub fn confirm(attempts: u64, threshold: u64, assume_yes: bool, config: &ScanConfig) -> bool {
+    if attempts <= threshold || assume_yes {
+        return true;
+    }
+
+    let estimate = format_estimate(estimate_worst_case(attempts, config));
+
+    if !std::io::stdin().is_terminal() {
+        eprintln!(
+            "Error: this scan makes {attempts} connection attempts (may take up to ~{estimate}), \
+             above the limit of {threshold}; pass --yes to run it non-interactively"
+        );
+        return false;
+    }
+
+    eprint!("This will make {attempts} connection attempts and may take up to ~{estimate}. Continue? [y/N] ");
+    let _ = std::io::stderr().flush();
+
+    let mut answer = String::new();
+    if std::io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim(), "y" | "Y" | "yes" | "Yes" | "YES")
+}
+
+/// Whether `ip` is in private or local address space, where scanning your
+/// own network is the normal case: loopback, unspecified, RFC 1918, shared
+/// (carrier-grade NAT, 100.64.0.0/10) and link-local IPv4; loopback,
+/// unspecified, unique local (fc00::/7) and link-local (fe80::/10) IPv6, and
+/// IPv4-mapped IPv6 addresses by their IPv4 part.
+pub fn is_private(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => is_private_v4(v4),
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_private_v4(&v4),
+            None => {
+                let first = v6.segments()[0];
+                v6.is_loopback() || v6.is_unspecified() || first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80
+            }
+        },
+    }
+}
+
+/// `is_private` for IPv4
+fn is_private_v4(ip: &Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_loopback() || ip.is_unspecified() || ip.is_private() || ip.is_link_local() || (a == 100 && (64..128).contains(&b))
+}
+
+/// Returns whether a scan of `ips` may go ahead: always when every target is
+/// private (see `is_private`), otherwise only when `acknowledged`. Public
+/// targets are named in a warning on stderr either way.
+pub fn allow_public(ips: &[IpAddr], acknowledged: bool) -> bool {
+    let public: Vec<&IpAddr> = ips.iter().filter(|ip| !is_private(ip)).collect();
+    let Some(first) = public.first() else {
+        return true;
+    };
+
+    let which = match public.len() {
+        1 => format!("{first} is a public address"),
+        2 => format!("{first} and 1 more target are public addresses"),
+        n => format!("{first} and {} more targets are public addresses", n - 1),
+    };
+    eprintln!("WARNING: {which}; only scan hosts you own or are authorized to test");
+    if !acknowledged {
+        eprintln!("Error: refusing to scan public addresses; pass --i-know-what-im-doing if you are authorized");
+    }
+    acknowledged
+}
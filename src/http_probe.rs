@@ -0,0 +1,83 @@
+//! HTTP fingerprinting for `--probe-http`.
+//!
+//! Each open port is sent a minimal `GET /` on a fresh connection. If the
+//! answer is an HTTP response, its `Server` header and the page `<title>`
+//! are kept. Plain HTTP only; TLS ports do not answer and are skipped.
+
+use serde::Serialize; // Probe results in JSON output
+use port_sniffer::route; // Source-bound connections
+use std::net::{IpAddr, SocketAddr}; // Probe targets and source address
+use tokio::io::{AsyncReadExt, AsyncWriteExt}; // Async read/write on streams
+use tokio::time::{Duration, Instant, timeout, timeout_at}; // Bound the connect and the exchange
+
+/// Maximum number of response bytes read, enough for headers and a `<head>`
+const MAX_RESPONSE: usize = 16 * 1024;
+/// Longest header or title kept, in characters
+const MAX_FIELD: usize = 120;
+
+/// What an HTTP server revealed about itself
+#[derive(Serialize)]
+pub struct HttpInfo {
+    /// Value of the `Server` response header
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    /// Text of the page's `<title>` element
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// Requests `/` from `target`, connecting from `source` if given, and
+/// returns the server and title, or `None` if the port did not answer with
+/// HTTP within the time and size limits. The connect may take
+/// `connect_timeout`; the request and response together `read_timeout`.
+pub async fn probe(target: SocketAddr, source: Option<IpAddr>, connect_timeout: Duration, read_timeout: Duration) -> Option<HttpInfo> {
+    let response = fetch(target, source, connect_timeout, read_timeout).await;
+    if !response.starts_with(b"HTTP/") {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&response);
+    let (head, body) = text.split_once("\r\n\r\n").unwrap_or((&text, ""));
+
+    let server = head.lines().skip(1).find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim().eq_ignore_ascii_case("server").then(|| clean(value))
+    });
+    Some(HttpInfo { server: server.filter(|s| !s.is_empty()), title: title(body) })
+}
+
+/// Sends the request and reads until the peer closes, `MAX_RESPONSE` bytes
+/// arrived or `read_timeout` ran out; whatever was read by then is kept, so
+/// a server that never closes still yields its headers.
+async fn fetch(target: SocketAddr, source: Option<IpAddr>, connect_timeout: Duration, read_timeout: Duration) -> Vec<u8> {
+    let mut response = Vec::new();
+    let Ok(Ok(mut stream)) = timeout(connect_timeout, route::tcp_connect(target, source)).await else {
+        return response;
+    };
+    let deadline = Instant::now() + read_timeout;
+    let request = format!("GET / HTTP/1.0\r\nHost: {target}\r\nUser-Agent: port_sniffer_cli\r\nConnection: close\r\n\r\n");
+    if !matches!(timeout_at(deadline, stream.write_all(request.as_bytes())).await, Ok(Ok(()))) {
+        return response;
+    }
+    let mut buf = vec![0; MAX_RESPONSE];
+    while response.len() < MAX_RESPONSE {
+        match timeout_at(deadline, stream.read(&mut buf[..MAX_RESPONSE - response.len()])).await {
+            Ok(Ok(0)) | Ok(Err(_)) | Err(_) => break,
+            Ok(Ok(n)) => response.extend_from_slice(&buf[..n]),
+        }
+    }
+    response
+}
+
+/// Text of the first `<title>` element in `body`, if any.
+fn title(body: &str) -> Option<String> {
+    let lower = body.to_ascii_lowercase();
+    let open = lower.find("<title")?;
+    let start = open + lower[open..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    Some(clean(&body[start..end])).filter(|t| !t.is_empty())
+}
+
+/// Collapses whitespace and caps the length of a header or title.
+fn clean(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ").chars().take(MAX_FIELD).collect()
+}
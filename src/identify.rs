@@ -0,0 +1,115 @@
+//! Lightweight protocol identification for open ports.
+//!
+//! Each open port is sent a short sequence of minimal probes on fresh
+//! connections and the first response is matched against a small table of
+//! signatures. The result depends only on what the service says, not on the
+//! port number, so an HTTP server on port 7777 is still reported as `http`.
+
+use port_sniffer::route; // Source-bound connections
+use std::net::{IpAddr, SocketAddr}; // Probe targets and source address
+use tokio::io::{AsyncReadExt, AsyncWriteExt}; // Async read/write on streams
+use tokio::time::{Duration, timeout}; // Bound every probe step
+
+/// Maximum number of response bytes inspected per probe
+const MAX_RESPONSE: usize = 512;
+
+/// Probe payloads in the order they are tried, each on a fresh connection.
+///
+/// The empty payload just waits for a greeting, which catches services that
+/// speak first (SSH, FTP, SMTP, ...) without sending anything.
+const PROBES: &[&[u8]] = &[b"", b"HEAD / HTTP/1.0\r\n\r\n", TLS_CLIENT_HELLO];
+
+/// Minimal TLS 1.2 ClientHello offering common ECDHE/RSA suites.
+///
+/// Servers answer with a ServerHello or an alert; both identify TLS.
+const TLS_CLIENT_HELLO: &[u8] = &[
+    0x16, 0x03, 0x01, 0x00, 0x57, // record: handshake, TLS 1.0, length 87
+    0x01, 0x00, 0x00, 0x53, // handshake: ClientHello, length 83
+    0x03, 0x03, // client version TLS 1.2
+    // 32 bytes of client random
+    0x50, 0x53, 0x4e, 0x46, 0x50, 0x53, 0x4e, 0x46, 0x50, 0x53, 0x4e, 0x46, 0x50, 0x53, 0x4e, 0x46,
+    0x50, 0x53, 0x4e, 0x46, 0x50, 0x53, 0x4e, 0x46, 0x50, 0x53, 0x4e, 0x46, 0x50, 0x53, 0x4e, 0x46,
+    0x00, // session id length
+    0x00, 0x0a, // cipher suites length
+    0xc0, 0x2f, 0xc0, 0x2b, 0xc0, 0x30, 0x00, 0x9c, 0x00, 0x2f,
+    0x01, 0x00, // compression methods: null
+    0x00, 0x20, // extensions length
+    0x00, 0x0a, 0x00, 0x06, 0x00, 0x04, 0x00, 0x17, 0x00, 0x1d, // supported groups
+    0x00, 0x0b, 0x00, 0x02, 0x01, 0x00, // ec point formats
+    0x00, 0x0d, 0x00, 0x0c, 0x00, 0x0a, 0x04, 0x01, 0x04, 0x03, 0x08, 0x04, 0x02, 0x01, 0x02,
+    0x03, // signature algorithms
+];
+
+/// Predicate deciding whether a response belongs to a protocol
+type Matcher = fn(&[u8]) -> bool;
+
+/// Response signatures, checked in order; the first match wins.
+const SIGNATURES: &[(&str, Matcher)] = &[
+    ("ssh", |r| r.starts_with(b"SSH-")),
+    ("http", |r| r.starts_with(b"HTTP/")),
+    ("tls", |r| r.len() >= 2 && matches!(r[0], 0x15 | 0x16) && r[1] == 0x03),
+    ("ftp", |r| r.starts_with(b"220") && contains_ignore_case(r, b"ftp")),
+    ("smtp", |r| r.starts_with(b"220")),
+    ("pop3", |r| r.starts_with(b"+OK")),
+    ("imap", |r| r.starts_with(b"* OK")),
+    ("vnc", |r| r.starts_with(b"RFB ")),
+    ("redis", |r| r.starts_with(b"-ERR") || r.starts_with(b"+PONG")),
+    ("mysql", |r| r.len() > 5 && r[4] == 0x0a),
+];
+
+/// Tries each probe against `target`, connecting from `source` if given,
+/// and returns the detected protocol, or `None` if no probe produced a
+/// recognizable response. Each connect may take `connect_timeout`, and each
+/// write and read `read_timeout`.
+pub async fn identify(
+    target: SocketAddr,
+    source: Option<IpAddr>,
+    connect_timeout: Duration,
+    read_timeout: Duration,
+) -> Option<&'static str> {
+    for payload in PROBES {
+        if let Some(response) = exchange(target, source, payload, connect_timeout, read_timeout).await
+            && let Some(protocol) = classify(&response)
+        {
+            return Some(protocol);
+        }
+    }
+    None
+}
+
+/// Matches a response against the signature table.
+pub fn classify(response: &[u8]) -> Option<&'static str> {
+    SIGNATURES
+        .iter()
+        .find(|(_, matches)| matches(response))
+        .map(|(name, _)| *name)
+}
+
+/// Opens a fresh connection, sends `payload` and returns the first chunk of
+/// the reply. Any failure or empty reply yields `None`.
+async fn exchange(
+    target: SocketAddr,
+    source: Option<IpAddr>,
+    payload: &[u8],
+    connect_timeout: Duration,
+    read_timeout: Duration,
+) -> Option<Vec<u8>> {
+    let mut stream = timeout(connect_timeout, route::tcp_connect(target, source)).await.ok()?.ok()?;
+
+    if !payload.is_empty() {
+        timeout(read_timeout, stream.write_all(payload)).await.ok()?.ok()?;
+    }
+
+    let mut buf = vec![0; MAX_RESPONSE];
+    let n = timeout(read_timeout, stream.read(&mut buf)).await.ok()?.ok()?;
+    if n == 0 {
+        return None;
+    }
+    buf.truncate(n);
+    Some(buf)
+}
+
+/// Case-insensitive ASCII substring search.
+fn contains_ignore_case(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w.eq_ignore_ascii_case(needle))
+}
@@ -0,0 +1,893 @@
+//! # port_sniffer
+//!
+//! The scanning engine behind `port_sniffer_cli`, usable as a library.
+//!
+//! Describe a scan with a `ScanConfig` and run it with `run_scan`, which
+//! returns one `ScanResult` per host:
+//!
+//! ```no_run
+//! use port_sniffer::{ScanConfig, run_scan};
+//! use std::time::Duration;
+//!
+//! # async fn example() {
+//! let config = ScanConfig::new("192.168.0.1".parse().unwrap(), (1..=1024).collect(), 50, Duration::from_secs(3));
+//! for result in run_scan(&config).await {
+//!     println!("{}: open {:?} in {:?}", result.ip, result.open, result.elapsed);
+//! }
+//! # }
+//! ```
+//!
+//! To follow a scan as it runs, e.g. to draw a progress display of your own,
+//! set `progress` to a callback; it gets a `ScanEvent` for every finished
+//! port:
+//!
+//! ```no_run
+//! # use port_sniffer::{ScanConfig, ScanEvent, run_scan};
+//! # use std::sync::Arc;
+//! # use std::time::Duration;
+//! # async fn example() {
+//! let mut config = ScanConfig::new("192.168.0.1".parse().unwrap(), (1..=1024).collect(), 50, Duration::from_secs(3));
+//! config.progress = Some(Arc::new(|event: ScanEvent| {
+//!     eprintln!("{}/{} done, {} open ({} is {})", event.done, event.total, event.open, event.port, event.outcome.name());
+//! }));
+//! run_scan(&config).await;
+//! # }
+//! ```
+
+pub mod adaptive; // Adaptive concurrency
+pub mod backoff; // Pauses between retries
+mod banner; // Service banner grabbing
+pub mod checkpoint; // Resume files
+pub mod emit; // Live result streaming over a Unix socket
+pub mod ramp; // Experimental connect-timeout ramp
+pub mod route; // Source address selection for --via
+pub mod sctp; // SCTP association probing
+pub mod socks; // SOCKS5 proxy connections
+mod udp; // UDP probing
+
+// Import required crates
+use adaptive::AdaptiveConcurrency; // Dynamically sized attempt pool
+use backoff::{Backoff, BackoffMode, DEFAULT_BACKOFF_MAX}; // Pauses between retries
+use checkpoint::Checkpoint; // Resume file appends
+use emit::SocketEmitter; // Live result stream
+use ramp::TimeoutRamp; // Experimental connect-timeout ramp
+use socks::Proxy; // Connections through a SOCKS5 proxy
+use clap::ValueEnum; // Protocol names on the command line
+use serde::Deserialize; // Protocol names in scan plans
+use std::collections::HashMap; // Banners by port, hosts by address
+use std::net::{IpAddr, SocketAddr, SocketAddrV6}; // IP and socket addresses
+use std::io::Write; // Flushing streamed lines
+use std::sync::Arc; // Thread-safe sharing
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering}; // Lock-free shared counters and flags
+use tokio::sync::{Mutex, Notify, mpsc}; // Rate ticker sharing; first open port signal; async multi-producer, single-consumer channel
+use tokio::time::{interval, sleep, timeout, Duration, Instant, MissedTickBehavior}; // Timeouts, pauses, pacing and timing for async operations
+use futures::future; // Ready futures for `take_while`
+use futures::stream::{self, StreamExt}; // for `for_each_concurrent` and `buffer_unordered` on streams
+use indicatif::ProgressBar; // Terminal progress bars
+use log::{debug, info, trace}; // Diagnostics for debugging the scanner
+
+/* -------------------------
+   Constants
+   ------------------------- */
+
+/// Simulated ports divisible by this are reported open
+const SIMULATED_OPEN_EVERY: u16 = 10;
+
+/// Extra attempts a timed-out port must also hang on to count as a tarpit
+const TARPIT_CONFIRMATIONS: usize = 2;
+
+/// Buffer size for the mpsc channel
+const CHANNEL_BUFFER_SIZE: usize = 250;
+
+/// Wait before the first retry of a port; later ones depend on the backoff
+/// mode (see `backoff`)
+pub const RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/* -------------------------
+   Asynchronous scan function
+   ------------------------- */
+
+/// Outcome of a single connection attempt
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    /// The connection was accepted
+    Open,
+    /// The connection was refused or failed outright
+    Closed,
+    /// Neither accepted nor refused before the timeout
+    TimedOut,
+}
+
+impl Outcome {
+    /// Status as written to resume files
+    pub fn name(self) -> &'static str {
+        match self {
+            Outcome::Open => "open",
+            Outcome::Closed => "closed",
+            Outcome::TimedOut => "timeout",
+        }
+    }
+
+    /// Status as logged by `verbose`; silence is open|filtered for UDP
+    fn label(self, protocol: Protocol) -> &'static str {
+        match (self, protocol) {
+            (Outcome::Open, _) => "open",
+            (Outcome::Closed, _) => "closed",
+            (Outcome::TimedOut, Protocol::Udp) => "open|filtered",
+            (Outcome::TimedOut, _) => "timeout",
+        }
+    }
+}
+
+/// What one connection attempt found out about a port
+struct Attempt {
+    /// Classification of the attempt
+    outcome: Outcome,
+    /// Time until the connection was accepted (or the probe answered), for
+    /// open ports
+    latency: Option<Duration>,
+    /// Greeting read after connecting, if asked for and sent
+    banner: Option<String>,
+}
+
+impl Attempt {
+    /// An attempt that ended without a connection
+    fn failed(outcome: Outcome) -> Self {
+        Self { outcome, latency: None, banner: None }
+    }
+}
+
+/// Progress report for one finished port, passed to `ScanConfig::progress`
+#[derive(Clone, Copy, Debug)]
+pub struct ScanEvent {
+    /// Host of the finished port
+    pub ip: IpAddr,
+    /// Finished port
+    pub port: u16,
+    /// Outcome of the port's last attempt
+    pub outcome: Outcome,
+    /// Host/port pairs finished so far in this scan, this one included
+    pub done: usize,
+    /// Host/port pairs in this scan
+    pub total: usize,
+    /// Open ports found so far, counting from `ScanConfig::open_count`'s
+    /// starting value
+    pub open: usize,
+}
+
+/// Callback invoked with a `ScanEvent` for every finished port
+pub type ProgressCallback = Arc<dyn Fn(ScanEvent) + Send + Sync>;
+
+/// Makes one connection attempt to `port` on `host` and classifies the
+/// result, together with the connect latency of an open port and its banner
+/// when `config.banner` is set and the service greeted the connection.
+///
+/// # Arguments
+///
+/// * `host` - Target address, scope and timeout
+/// * `port` - Port number to test
+/// * `config` - Protocol and test-hook settings
+async fn connect_once(host: &HostTarget, port: u16, config: &ScanConfig) -> Attempt {
+    let target = socket_addr(host.ip, port, host.scope_id);
+    let connect_timeout = host.ramp.as_ref().map_or(host.connect_timeout, |ramp| ramp.current());
+    match config.simulate_latency {
+        // Deterministic pattern so tests can predict the result
+        Some(latency) => {
+            sleep(latency).await;
+            if port.is_multiple_of(SIMULATED_OPEN_EVERY) {
+                Attempt { outcome: Outcome::Open, latency: Some(latency), banner: None }
+            } else {
+                Attempt::failed(Outcome::Closed)
+            }
+        }
+        None => {
+            trace!("{target}/{}: connecting, timeout {connect_timeout:?}", config.protocol.name());
+            let started = Instant::now();
+            let result = match config.protocol {
+                Protocol::Tcp => match &config.proxy {
+                    Some(proxy) => timeout(connect_timeout, proxy.connect(target)).await.map(|r| r.map(Some)),
+                    None => timeout(connect_timeout, route::tcp_connect(target, config.source)).await.map(|r| r.map(Some)),
+                },
+                Protocol::Sctp => timeout(connect_timeout, sctp::connect(target, config.source)).await.map(|r| r.map(|()| None)),
+                // No reply within the timeout counts as timed out, i.e. open|filtered
+                Protocol::Udp => timeout(connect_timeout, udp::probe(target, config.source)).await.map(|r| r.map(|()| None)),
+            };
+            let latency = started.elapsed();
+            if let Some(ramp) = &host.ramp {
+                ramp.observe(result.is_ok().then_some(latency));
+            }
+            match result {
+                // Ok(Ok(_)) = connection succeeded before timeout
+                Ok(Ok(stream)) => {
+                    let banner = match (stream, config.wait_before_close) {
+                        (Some(stream), Some(wait)) => banner::hold(stream, wait, config.banner_bytes).await,
+                        (Some(stream), None) if config.banner => banner::read(stream, config.banner_bytes, config.banner_timeout).await,
+                        _ => None,
+                    };
+                    Attempt { outcome: Outcome::Open, latency: Some(latency), banner }
+                }
+                // A proxy reports its own connect timeout as an error
+                Ok(Err(e)) if e.kind() == std::io::ErrorKind::TimedOut => Attempt::failed(Outcome::TimedOut),
+                Ok(Err(_)) => Attempt::failed(Outcome::Closed),
+                Err(_) => Attempt::failed(Outcome::TimedOut),
+            }
+        }
+    }
+}
+
+/// Attempts to connect to a given IP and port asynchronously, retrying up
+/// to `config.retries` times with a pause set by `config.backoff` while it does not connect.
+/// 
+/// Every port's outcome is sent through the mpsc channel with the host's
+/// position in `config.hosts`, open ones with their latency and banner (if
+/// grabbed); the collector turns it into a progress event. A port counts as
+/// timed out when its last attempt did.
+///
+/// # Arguments
+///
+/// * `tx` - Channel sender to report each port's attempt
+/// * `target` - Position of the target's host in `config.hosts` and port number to test
+/// * `pb` - Shared progress bar, for the in-flight message
+/// * `config` - Retry and test-hook settings
+/// * `in_flight` - Shared count of connection attempts currently running
+async fn scan(
+    tx: mpsc::Sender<(usize, u16, Attempt)>,
+    target: (usize, u16),
+    pb: Arc<ProgressBar>,
+    config: &ScanConfig,
+    in_flight: Arc<AtomicUsize>,
+) {
+    let (index, port) = target;
+    let host = &config.hosts[index];
+    let addr = host.ip;
+
+    // In adaptive mode, wait for a slot under the current limit; a scan
+    // stopped meanwhile starts nothing more
+    let permit = match &config.adaptive {
+        Some(adaptive) => Some(adaptive.acquire().await),
+        None => None,
+    };
+    if permit.is_some() && config.stop.load(Ordering::Relaxed) {
+        return;
+    }
+
+    // Show how many attempts are running, to tell whether concurrency is saturated
+    let running = in_flight.fetch_add(1, Ordering::Relaxed) + 1;
+    pb.set_message(in_flight_message(running, config));
+
+    let mut attempt = connect_once(host, port, config).await;
+    for retry in 1..=config.retries {
+        if attempt.outcome == Outcome::Open || config.stop.load(Ordering::Relaxed) {
+            break;
+        }
+        debug!("{addr} port {port}: {}, retry {retry} of {}", attempt.outcome.name(), config.retries);
+        sleep(config.backoff.delay(addr, port, retry - 1)).await;
+        attempt = connect_once(host, port, config).await;
+    }
+    let outcome = attempt.outcome;
+    debug!("{addr} port {port}: {}", outcome.name());
+    if let Some(ramp) = &host.ramp {
+        ramp.port_done();
+    }
+
+    if let (Some(adaptive), Some(permit)) = (&config.adaptive, permit) {
+        adaptive.finish(permit, outcome == Outcome::TimedOut);
+    }
+    let running = in_flight.fetch_sub(1, Ordering::Relaxed) - 1;
+    pb.set_message(in_flight_message(running, config));
+
+    if let Some(checkpoint) = &config.checkpoint {
+        checkpoint.record(addr, port, outcome.name());
+    }
+
+    if config.verbose {
+        let target = socket_addr(addr, port, host.scope_id);
+        pb.suspend(|| eprintln!("{target}/{} {}", config.protocol.name(), outcome.label(config.protocol)));
+    }
+
+    // Send the port to the channel (ignore failure); the collector reports
+    // the progress
+    let _ = tx.send((index, port, attempt)).await;
+}
+
+/// Progress bar message: the open ports found so far and the `running`
+/// attempts, with the adaptive limit
+fn in_flight_message(running: usize, config: &ScanConfig) -> String {
+    let open = config.open_count.load(Ordering::Relaxed);
+    match &config.adaptive {
+        Some(adaptive) => format!("{open} open, {running} in flight (limit {})", adaptive.limit()),
+        None => format!("{open} open, {running} in flight"),
+    }
+}
+
+/// Connects to `target` (from `source`, if given) and reads its greeting as
+/// `config.banner` does during the scan, for a banner read after other
+/// probes. The connect may take `connect_timeout`, the read `wait`.
+pub async fn grab_banner(target: SocketAddr, source: Option<IpAddr>, connect_timeout: Duration, max_bytes: usize, wait: Duration) -> Option<String> {
+    let stream = timeout(connect_timeout, route::tcp_connect(target, source)).await.ok()?.ok()?;
+    banner::read(stream, max_bytes, wait).await
+}
+
+/// Socket address for `addr:port`, on interface `scope_id` when it is an
+/// IPv6 address and the index is non-zero.
+pub fn socket_addr(addr: IpAddr, port: u16, scope_id: u32) -> SocketAddr {
+    match addr {
+        IpAddr::V6(v6) if scope_id != 0 => SocketAddr::V6(SocketAddrV6::new(v6, port, 0, scope_id)),
+        _ => SocketAddr::new(addr, port),
+    }
+}
+
+/// Line format for open ports printed while the scan runs
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    /// `10.0.0.1:22/tcp open`
+    Text,
+    /// `{"ip":"10.0.0.1","port":22,"status":"open"}`
+    JsonLines,
+}
+
+/// One open port as a JSON line, without the newline
+pub fn json_line(ip: IpAddr, port: u16) -> String {
+    format!("{{\"ip\":\"{ip}\",\"port\":{port},\"status\":\"open\"}}")
+}
+
+/// Transport protocol probed on each port
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    /// TCP connect scan
+    Tcp,
+    /// SCTP association (INIT) scan
+    Sctp,
+    /// UDP datagram probe; silent ports are open|filtered
+    Udp,
+}
+
+impl Protocol {
+    /// Lower-case protocol name, as used in `22/sctp`
+    pub fn name(self) -> &'static str {
+        match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Sctp => "sctp",
+            Protocol::Udp => "udp",
+        }
+    }
+}
+
+/// One host of a scan, with the settings that are tuned per host.
+pub struct HostTarget {
+    /// Target IP address
+    pub ip: IpAddr,
+    /// Interface index for a scoped (e.g. link-local) IPv6 target, 0 for none
+    pub scope_id: u32,
+    /// Ports to test, in the order they should be attempted
+    pub ports: Vec<u16>,
+    /// How long each connection attempt may take (the cap when ramping)
+    pub connect_timeout: Duration,
+    /// Ramp the timeout up from a short start (see `ramp`)
+    pub ramp: Option<TimeoutRamp>,
+}
+
+impl HostTarget {
+    /// `ports` on `ip` with a fixed timeout and no scope.
+    pub fn new(ip: IpAddr, ports: Vec<u16>, connect_timeout: Duration) -> Self {
+        Self { ip, scope_id: 0, ports, connect_timeout, ramp: None }
+    }
+}
+
+/// Everything that describes a scan of one or more hosts.
+///
+/// `ScanConfig::new` fills in defaults for a plain TCP scan of one host; the
+/// other fields can be set afterwards.
+pub struct ScanConfig {
+    /// Hosts to scan; their ports share the concurrency and rate limits
+    pub hosts: Vec<HostTarget>,
+    /// Transport protocol to probe
+    pub protocol: Protocol,
+    /// Local address to connect from, if pinned
+    pub source: Option<IpAddr>,
+    /// SOCKS5 proxy that makes the TCP connections, if any
+    pub proxy: Option<Proxy>,
+    /// Maximum number of connection attempts in flight
+    pub concurrency: usize,
+    /// Adjust the attempts in flight to the timeout rate, up to
+    /// `concurrency` (see `adaptive`)
+    pub adaptive: Option<AdaptiveConcurrency>,
+    /// Maximum number of connection attempts started per second, if limited
+    pub rate: Option<u32>,
+    /// Number of host/port pairs per batch (all of them when not chunking)
+    pub chunk_size: usize,
+    /// Pause between batches
+    pub chunk_pause: Duration,
+    /// Take one port from each host in turn instead of all of a host's
+    /// ports before the next host's (see `pairs`)
+    pub interleave: bool,
+    /// Read each open TCP port's greeting (see `banner`)
+    pub banner: bool,
+    /// Most bytes read from a service, for banners and held connections
+    pub banner_bytes: usize,
+    /// How long to wait for a greeting
+    pub banner_timeout: Duration,
+    /// Hold each open TCP connection this long, keeping what the service
+    /// sends as its banner
+    pub wait_before_close: Option<Duration>,
+    /// Extra attempts for a port that did not connect
+    pub retries: usize,
+    /// Pause before each retry
+    pub backoff: Backoff,
+    /// Log every port's result to stderr as it is known
+    pub verbose: bool,
+    /// Print each open port to stdout as soon as it is found, in this format
+    pub stream: Option<StreamFormat>,
+    /// Resume file that every finished port is appended to
+    pub checkpoint: Option<Arc<Checkpoint>>,
+    /// Test hook: sleep instead of connecting (see `scan`)
+    pub simulate_latency: Option<Duration>,
+    /// Running count of open ports found, shared with progress reporters
+    pub open_count: Arc<AtomicUsize>,
+    /// Called with every finished port, in the order the results come in
+    pub progress: Option<ProgressCallback>,
+    /// End the scan once this many open ports are found (counting
+    /// `open_count`'s starting value), abandoning the attempts in flight
+    /// instead of letting them finish; `Some(1)` stops at the first one
+    pub max_open: Option<usize>,
+    /// Once set, no new connection attempts or retries are started;
+    /// attempts already in flight still finish (used for Ctrl-C and time
+    /// limits)
+    pub stop: Arc<AtomicBool>,
+}
+
+impl ScanConfig {
+    /// A TCP scan of `ports` on `ip` in a single batch, without banners,
+    /// source binding, proxy or timeout ramp.
+    pub fn new(ip: IpAddr, ports: Vec<u16>, concurrency: usize, connect_timeout: Duration) -> Self {
+        Self {
+            chunk_size: ports.len(),
+            hosts: vec![HostTarget::new(ip, ports, connect_timeout)],
+            protocol: Protocol::Tcp,
+            source: None,
+            proxy: None,
+            concurrency,
+            adaptive: None,
+            rate: None,
+            chunk_pause: Duration::ZERO,
+            interleave: false,
+            banner: false,
+            banner_bytes: banner::DEFAULT_BYTES,
+            banner_timeout: banner::DEFAULT_TIMEOUT,
+            wait_before_close: None,
+            retries: 0,
+            backoff: Backoff::new(BackoffMode::Exponential, RETRY_BACKOFF, DEFAULT_BACKOFF_MAX, 0),
+            verbose: false,
+            stream: None,
+            checkpoint: None,
+            simulate_latency: None,
+            open_count: Arc::new(AtomicUsize::new(0)),
+            progress: None,
+            max_open: None,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Every host/port pair as the host's position in `hosts` and the port,
+    /// in attempt order. Host-major by default: all of the first host's
+    /// ports, then the next host's. With `interleave` one port is taken from
+    /// each host in turn, so the attempts in flight at any time are spread
+    /// over all hosts and a slow host cannot hold up the others. Each host's
+    /// ports keep their configured order either way.
+    fn pairs(&self) -> Vec<(usize, u16)> {
+        if !self.interleave {
+            return self.hosts.iter().enumerate().flat_map(|(index, host)| host.ports.iter().map(move |&port| (index, port))).collect();
+        }
+        let longest = self.hosts.iter().map(|host| host.ports.len()).max().unwrap_or(0);
+        (0..longest)
+            .flat_map(|i| {
+                self.hosts.iter().enumerate().filter_map(move |(index, host)| host.ports.get(i).map(|&port| (index, port)))
+            })
+            .collect()
+    }
+}
+
+/// Ports found on one host, each list in ascending order.
+pub struct ScanResult {
+    /// Host the ports belong to
+    pub ip: IpAddr,
+    /// Number of ports attempted (fewer than configured if stopped early)
+    pub scanned: usize,
+    /// The ports that were attempted, ascending; when stopped early these
+    /// are whichever attempts finished, not a prefix of the host's list
+    pub attempted: Vec<u16>,
+    /// Ports that accepted a connection
+    pub open: Vec<u16>,
+    /// Ports whose connection attempt timed out
+    pub timed_out: Vec<u16>,
+    /// Banners of the open ports that sent one
+    pub banners: HashMap<u16, String>,
+    /// Connect latency of each open port
+    pub latencies: HashMap<u16, Duration>,
+    /// Time from the start of the scan until the host's last port finished,
+    /// including chunk pauses
+    pub elapsed: Duration,
+}
+
+/// Runs the scan described by `config` without a progress bar; progress is
+/// only reported through `config.progress`, if set.
+pub async fn run_scan(config: &ScanConfig) -> Vec<ScanResult> {
+    scan_ports(config, Arc::new(ProgressBar::hidden()), None).await
+}
+
+/// Runs the scan described by `config` and returns the open and timed-out
+/// ports of each host, in the order of `config.hosts`.
+///
+/// All hosts share one stream of host/port pairs (host-major, or
+/// interleaved with `config.interleave`), so `concurrency` and `rate` are
+/// budgets for the whole scan rather than per host. Open ports are collected while the scan
+/// runs, so each one is streamed to `emitter` (if any) as soon as it is found.
+///
+/// # Arguments
+///
+/// * `config` - Targets, concurrency and pacing settings
+/// * `pb` - Shared progress bar, for the in-flight message and printing
+///   around it; progress itself goes to `config.progress`
+/// * `emitter` - Optional live result stream
+pub async fn scan_ports(
+    config: &ScanConfig,
+    pb: Arc<ProgressBar>,
+    mut emitter: Option<&mut SocketEmitter>,
+) -> Vec<ScanResult> {
+    let pairs = config.pairs();
+    let total = pairs.len();
+    info!(
+        "scanning {} {} host/port pairs on {} host(s) (concurrency {})",
+        pairs.len(),
+        config.protocol.name(),
+        config.hosts.len(),
+        config.concurrency
+    );
+    let started = Instant::now();
+    // Create channel for collecting results
+    let (tx, mut rx) = mpsc::channel(CHANNEL_BUFFER_SIZE);
+    let in_flight = Arc::new(AtomicUsize::new(0));
+
+    let stream_pb = pb.clone();
+    let collector_in_flight = in_flight.clone();
+
+    // Testing aid: one attempt at a time, strictly in list order
+    let concurrency = if cfg!(feature = "deterministic") { 1 } else { config.concurrency };
+
+    // Evenly spaced launch slots for `rate`; a late slot (e.g. after a chunk
+    // pause) is not made up with a burst
+    let ticker = config.rate.map(|rate| {
+        let mut ticker = interval(Duration::from_secs(1) / rate.max(1));
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Mutex::new(ticker)
+    });
+    let ticker_ref = &ticker;
+
+    // Raised by the collector when `max_open` is reached
+    let enough_open = Notify::new();
+    let enough_open_ref = &enough_open;
+
+    let scanning = async move {
+        let launching = async {
+            for (i, chunk) in pairs.chunks(config.chunk_size.max(1)).enumerate() {
+                // Pause between batches, but not before the first one
+                if i > 0 {
+                    debug!("pausing {:?} before batch {}", config.chunk_pause, i + 1);
+                    sleep(config.chunk_pause).await;
+                }
+                if config.stop.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                // Scan this batch concurrently with the specified limit, starting
+                // no new attempts once stopped; each pair waits for its launch
+                // slot before it is handed out
+                tokio_stream::iter(chunk.iter().copied())
+                    .then(|target| async move {
+                        if let Some(ticker) = ticker_ref {
+                            ticker.lock().await.tick().await;
+                        }
+                        target
+                    })
+                    .take_while(|_| future::ready(!config.stop.load(Ordering::Relaxed)))
+                    .for_each_concurrent(concurrency, |target| {
+                        let tx = tx.clone();
+                        let pb = pb.clone();
+                        let in_flight = in_flight.clone();
+                        async move {
+                            scan(tx, target, pb, config, in_flight).await;
+                        }
+                    })
+                    .await;
+            }
+        };
+
+        // Dropping the launcher drops the attempts in flight with it
+        tokio::select! {
+            () = launching => {}
+            () = enough_open_ref.notified() => debug!("stopping: enough open ports found"),
+        }
+
+        drop(tx); // Close the channel when all tasks finish
+    };
+
+    // Collect ports from the channel as they arrive, flushing the result
+    // stream periodically so slow trickles still reach the collector
+    let collecting = async {
+        let mut reports: Vec<ScanResult> = config
+            .hosts
+            .iter()
+            .map(|host| ScanResult {
+                ip: host.ip,
+                scanned: 0,
+                attempted: vec![],
+                open: vec![],
+                timed_out: vec![],
+                banners: HashMap::new(),
+                latencies: HashMap::new(),
+                elapsed: Duration::ZERO,
+            })
+            .collect();
+        let period = emitter.as_ref().map_or(Duration::from_secs(1), |e| e.flush_interval());
+        let mut flush_ticker = interval(period);
+        let mut done = 0;
+
+        loop {
+            tokio::select! {
+                received = rx.recv() => {
+                    let Some((index, port, attempt)) = received else { break };
+                    let report = &mut reports[index];
+                    let addr = report.ip;
+                    report.scanned += 1;
+                    report.attempted.push(port);
+                    report.elapsed = started.elapsed();
+                    done += 1;
+                    let found = if attempt.outcome == Outcome::Open {
+                        config.open_count.fetch_add(1, Ordering::Relaxed) + 1
+                    } else {
+                        config.open_count.load(Ordering::Relaxed)
+                    };
+                    if let Some(progress) = &config.progress {
+                        progress(ScanEvent { ip: addr, port, outcome: attempt.outcome, done, total, open: found });
+                    }
+                    match attempt.outcome {
+                        Outcome::Closed => continue,
+                        Outcome::TimedOut => {
+                            report.timed_out.push(port);
+                            continue;
+                        }
+                        Outcome::Open => {}
+                    }
+                    if let Some(emitter) = emitter.as_deref_mut() {
+                        emitter.emit(addr, port).await;
+                    }
+                    // To stdout with the progress bar (on stderr) cleared meanwhile,
+                    // flushed so a consumer at the other end of a pipe sees it now
+                    if let Some(format) = config.stream {
+                        let line = match format {
+                            StreamFormat::Text => {
+                                let target = socket_addr(addr, port, config.hosts[index].scope_id);
+                                format!("{target}/{} open", config.protocol.name())
+                            }
+                            StreamFormat::JsonLines => json_line(addr, port),
+                        };
+                        stream_pb.suspend(|| {
+                            let mut stdout = std::io::stdout().lock();
+                            let _ = writeln!(stdout, "{line}").and_then(|()| stdout.flush());
+                        });
+                    }
+                    report.open.push(port);
+                    if let Some(latency) = attempt.latency {
+                        report.latencies.insert(port, latency);
+                    }
+                    if let Some(banner) = attempt.banner {
+                        report.banners.insert(port, banner);
+                    }
+                    // Show the new count now rather than at the next attempt
+                    stream_pb.set_message(in_flight_message(collector_in_flight.load(Ordering::Relaxed), config));
+                    if config.max_open.is_some_and(|max| found >= max) {
+                        config.stop.store(true, Ordering::Relaxed);
+                        enough_open.notify_one();
+                        break;
+                    }
+                }
+                _ = flush_ticker.tick(), if emitter.is_some() => {
+                    if let Some(emitter) = emitter.as_deref_mut() {
+                        emitter.flush().await;
+                    }
+                }
+            }
+        }
+
+        if let Some(emitter) = emitter.as_deref_mut() {
+            emitter.flush().await;
+        }
+        reports
+    };
+
+    let ((), mut reports) = tokio::join!(scanning, collecting);
+
+    for report in &mut reports {
+        report.attempted.sort_unstable();
+        report.open.sort();
+        report.timed_out.sort();
+        info!(
+            "finished {}: {} scanned, {} open, {} timed out in {:?}",
+            report.ip,
+            report.scanned,
+            report.open.len(),
+            report.timed_out.len(),
+            report.elapsed
+        );
+    }
+    reports
+}
+
+/// Second pass over `host`'s timed-out ports, against false negatives: each
+/// is tried again with the longer `connect_timeout` and up to `retries`
+/// retries, stopping at the first attempt that is accepted or refused.
+///
+/// The result covers only the rechecked ports: those that connected (with
+/// latency and banner), and in `timed_out` those that stayed silent; the
+/// rest were refused, i.e. closed. Once `config.stop` is set, the ports
+/// not yet tried are kept as timed out without a new attempt.
+pub async fn verify_timeouts(
+    config: &ScanConfig,
+    host: &HostTarget,
+    timed_out: &[u16],
+    connect_timeout: Duration,
+    retries: usize,
+) -> ScanResult {
+    let started = Instant::now();
+    // A fixed, longer timeout: the ramp has done its part in the main pass
+    let patient = HostTarget { ip: host.ip, scope_id: host.scope_id, ports: vec![], connect_timeout, ramp: None };
+    let patient = &patient;
+    let attempts: Vec<(u16, Attempt)> = stream::iter(timed_out.iter().copied())
+        .map(|port| async move {
+            if config.stop.load(Ordering::Relaxed) {
+                return (port, Attempt::failed(Outcome::TimedOut));
+            }
+            let mut attempt = connect_once(patient, port, config).await;
+            for retry in 1..=retries {
+                if attempt.outcome != Outcome::TimedOut || config.stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                sleep(config.backoff.delay(host.ip, port, retry - 1)).await;
+                attempt = connect_once(patient, port, config).await;
+            }
+            debug!("{} port {port}: {} on the verify pass", host.ip, attempt.outcome.name());
+            (port, attempt)
+        })
+        .buffer_unordered(config.concurrency)
+        .collect()
+        .await;
+
+    let mut result = ScanResult {
+        ip: host.ip,
+        scanned: timed_out.len(),
+        attempted: timed_out.to_vec(),
+        open: vec![],
+        timed_out: vec![],
+        banners: HashMap::new(),
+        latencies: HashMap::new(),
+        elapsed: started.elapsed(),
+    };
+    for (port, attempt) in attempts {
+        match attempt.outcome {
+            Outcome::Open => result.open.push(port),
+            Outcome::TimedOut => result.timed_out.push(port),
+            Outcome::Closed => continue,
+        }
+        if let Some(latency) = attempt.latency {
+            result.latencies.insert(port, latency);
+        }
+        if let Some(banner) = attempt.banner {
+            result.banners.insert(port, banner);
+        }
+    }
+    result.open.sort_unstable();
+    result.timed_out.sort_unstable();
+    result
+}
+
+/// Re-tries each of `host`'s timed-out ports `TARPIT_CONFIRMATIONS` more times and returns
+/// the ones that hung on every attempt, in ascending order.
+///
+/// A port that is refused or accepted on any retry is not reported, so only
+/// consistent hangs count as a possible tarpit.
+pub async fn confirm_tarpits(config: &ScanConfig, host: &HostTarget, timed_out: &[u16]) -> Vec<u16> {
+    let mut tarpits: Vec<u16> = stream::iter(timed_out.iter().copied())
+        .map(|port| async move {
+            for _ in 0..TARPIT_CONFIRMATIONS {
+                if connect_once(host, port, config).await.outcome != Outcome::TimedOut {
+                    return None;
+                }
+            }
+            Some(port)
+        })
+        .buffer_unordered(config.concurrency)
+        .filter_map(|port| async move { port })
+        .collect()
+        .await;
+
+    tarpits.sort();
+    tarpits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const SLOW: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    const FAST: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+    /// A slow host with many ports listed before a fast one with a few
+    fn sweep(interleave: bool) -> ScanConfig {
+        let timeout = Duration::from_secs(1);
+        let mut config = ScanConfig::new(SLOW, (1..=100).collect(), 4, timeout);
+        config.hosts.push(HostTarget::new(FAST, vec![22, 80, 443], timeout));
+        config.interleave = interleave;
+        config
+    }
+
+    #[test]
+    fn host_major_finishes_each_host_first() {
+        let pairs = sweep(false).pairs();
+        assert_eq!(pairs.len(), 103);
+        assert!(pairs[..100].iter().all(|&(host, _)| host == 0));
+        assert_eq!(pairs[100..], [(1, 22), (1, 80), (1, 443)]);
+    }
+
+    #[test]
+    fn interleaving_keeps_a_slow_host_from_starving_the_others() {
+        let config = sweep(true);
+        let pairs = config.pairs();
+        assert_eq!(pairs.len(), 103);
+        // The first `concurrency` attempts already reach the fast host, and
+        // all of its ports are out before the slow host's fourth
+        assert!(pairs[..config.concurrency].iter().any(|&(host, _)| host == 1));
+        assert_eq!(pairs[..6], [(0, 1), (1, 22), (0, 2), (1, 80), (0, 3), (1, 443)]);
+        assert!(pairs[6..].iter().all(|&(host, _)| host == 0));
+    }
+
+    #[test]
+    fn each_host_keeps_its_port_order() {
+        for interleave in [false, true] {
+            let pairs = sweep(interleave).pairs();
+            let slow: Vec<u16> = pairs.iter().filter(|&&(host, _)| host == 0).map(|&(_, port)| port).collect();
+            assert_eq!(slow, (1..=100).collect::<Vec<u16>>());
+        }
+    }
+
+    #[tokio::test]
+    async fn hosts_share_one_pool_of_attempts() {
+        // Six ports on one host and two on another, four attempts at a time
+        // of 100ms each: the second host's ports fill the slots the first
+        // host leaves free, so everything is done in two rounds instead of
+        // the three that scanning the hosts one after another would take
+        let timeout = Duration::from_secs(1);
+        let mut config = ScanConfig::new(SLOW, (1..=6).collect(), 4, timeout);
+        config.hosts.push(HostTarget::new(FAST, vec![22, 80], timeout));
+        config.chunk_size = 8;
+        config.simulate_latency = Some(Duration::from_millis(100));
+        let results = run_scan(&config).await;
+        let longest = results.iter().map(|result| result.elapsed).max().expect("two hosts");
+        assert_eq!(results.iter().map(|result| result.scanned).sum::<usize>(), 8);
+        assert!(longest < Duration::from_millis(280), "took {longest:?}");
+    }
+
+    #[tokio::test]
+    async fn interleaved_sweep_finishes_the_fast_host_early() {
+        // Simulated attempts take 20ms each; two at a time
+        let mut config = sweep(true);
+        config.concurrency = 2;
+        config.simulate_latency = Some(Duration::from_millis(20));
+        let results = run_scan(&config).await;
+        let (slow, fast) = (&results[0], &results[1]);
+        assert_eq!(fast.scanned, 3);
+        assert_eq!(slow.scanned, 100);
+        assert!(fast.elapsed * 10 < slow.elapsed, "fast {:?}, slow {:?}", fast.elapsed, slow.elapsed);
+    }
+}
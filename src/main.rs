@@ -9,15 +9,62 @@
 //! port_sniffer_cli --ip 192.168.0.1 --start_port 1 --end_port 1024 --concurrency 50
 //! ```
 
+mod bar_style; // Progress bar presets
+mod calibrate; // RTT-based timeout calibration
+mod cidr; // CIDR target expansion
+mod color; // Colored text report
+mod config; // Saved defaults in a TOML file
+mod db; // SQLite export
+mod duration; // Human-friendly duration flags
+mod fd_limit; // Open file limit for high concurrency
+mod guard; // Confirmation for very large scans
+mod http_probe; // HTTP server and title for open ports
+mod identify; // Protocol detection for open ports
+mod plan; // JSON scan plans
+mod probe_order; // `--probe-order` and when probes are skipped
+mod progress_fd; // Machine-readable progress feed
+mod ranges; // Coalescing ports into ranges
+mod report; // Final report and output formats
+mod reproduce; // Normalized reproduction command line
+mod selftest; // `selftest` subcommand
+mod services; // Well-known service names
+mod tls_probe; // TLS handshakes with open ports
+mod top_ports; // Most commonly open ports
+
 // Import required crates
-use clap::{Arg, Command, value_parser}; // CLI argument parsing
-use std::net::IpAddr; // Represents an IP address
-use std::sync::Arc; // Atomic reference-counted pointer for thread-safe sharing
-use tokio::net::TcpStream; // Asynchronous TCP connections using Tokio
-use tokio::sync::mpsc; // Async multi-producer, single-consumer channel
-use tokio::time::{timeout, Duration}; // Set timeouts for async operations
-use futures::stream::StreamExt; // for `for_each_concurrent` on streams
-use indicatif::{ProgressBar, ProgressStyle}; // Terminal progress bars
+use bar_style::BarStyle; // `--progress-style` presets
+use cidr::{Endpoint, Family, Target}; // `--ip`, `--target` and `--family` values
+use color::{ColorChoice, Palette}; // `--color` and report colors
+use port_sniffer::checkpoint::{self, Checkpoint}; // Resume files
+use port_sniffer::emit::SocketEmitter; // Live result stream
+use rand::SeedableRng; // Seeding the shuffle
+use rand::rngs::StdRng; // Reproducible shuffle for --randomize
+use rand::seq::SliceRandom; // Shuffling the port order
+use port_sniffer::adaptive::AdaptiveConcurrency; // `--adaptive`
+use port_sniffer::backoff::{Backoff, BackoffMode}; // `--backoff-mode` and `--backoff-max`
+use port_sniffer::ramp::TimeoutRamp; // Experimental connect-timeout ramp
+use port_sniffer::socks::{Proxy, ProxyUrl}; // `--proxy`
+use port_sniffer::{HostTarget, Protocol, RETRY_BACKOFF, ScanConfig, ScanEvent, ScanResult, StreamFormat, confirm_tarpits, grab_banner, json_line, route, scan_ports, sctp, socket_addr, verify_timeouts}; // Scanning engine
+use probe_order::{Kind, Probe}; // Follow-up probes of open ports
+use progress_fd::ProgressFeed; // Machine-readable progress feed
+use report::{Format, HostReport, OpenPort, Report, TextOptions, count}; // Final report
+use clap::parser::ValueSource; // Telling explicit flags from defaults
+use clap::builder::BoolishValueParser; // Switching flags on from the environment
+use clap::{Arg, ArgAction, ArgMatches, Command, value_parser}; // CLI argument parsing
+use std::collections::{HashMap, HashSet}; // Interface index by target; finished ports
+use std::fs::File; // Report file
+use std::io::IsTerminal; // Hiding the progress bar when piped
+use std::io::Write as _; // Writing the report file
+use std::net::{IpAddr, SocketAddr}; // IP and socket addresses
+use std::path::PathBuf; // Owned filesystem paths
+use std::sync::{Arc, Mutex}; // Thread-safe sharing
+use std::sync::atomic::{AtomicBool, Ordering}; // Shared open port counter and stop reasons
+use std::time::SystemTime; // Wall-clock start time for exports
+use tokio::time::{interval, sleep, Duration, Instant}; // Progress ticks, time limit and timing
+use futures::stream::{self, StreamExt}; // for `buffered` on streams
+use indicatif::{ProgressBar, ProgressDrawTarget}; // Terminal progress bars
+use console::Term; // Drawing a forced progress bar on a non-terminal
+use log::{debug, info}; // Diagnostics for debugging the scanner
 
 /* -------------------------
    Constants
@@ -31,20 +78,148 @@ const VERSION: &str = "1.0";
 const AUTHOR: &str = "Sinameru";
 /// About description
 const ABOUT: &str = "Simple port scanner CLI";
+/// Prefix of the environment variables that stand in for flags, e.g.
+/// `PORT_SNIFFER_CONCURRENCY` for `--concurrency`
+const ENV_PREFIX: &str = "PORT_SNIFFER_";
+
+/// Long name for the configuration file
+const LONG_CONFIG: &str = "config";
+/// Help message for the configuration file
+const HELP_CONFIG: &str = "Read default concurrency, timeout, format and ports from a TOML file \
+(default: ~/.config/port_sniffer_cli/config.toml if it exists); flags override it";
+
+/// Long name for the scan plan
+const LONG_PLAN: &str = "plan";
+/// Help message for the scan plan
+const HELP_PLAN: &str = "Read targets, ports and options from a JSON scan plan; explicit flags override it";
 
 /// Long name for IP argument
 const LONG_IP: &str = "ip";
 /// Help message for IP argument
-const HELP_IP: &str = "Target IP address";
+const HELP_IP: &str = "Target IP address, CIDR range (e.g. 192.168.1.0/24) or host name; repeat the flag or separate targets with commas to scan several";
+
+/// Long name for the target list file
+const LONG_TARGET_FILE: &str = "target-file";
+/// Help message for the target list file
+const HELP_TARGET_FILE: &str = "Read more targets from a file (- for stdin): one address, CIDR range or host name per line, # starts a comment";
+
+/// Long name for the single socket address check
+const LONG_TARGET: &str = "target";
+/// Help message for the single socket address check
+const HELP_TARGET: &str = "Check one ADDR:PORT (e.g. 10.0.0.1:22 or [fe80::1%eth0]:22) instead of scanning ranges, \
+and print whether it is open, closed or filtered";
+
+/// Long name for scanning every resolved address
+const LONG_ALL_ADDRESSES: &str = "all-addresses";
+/// Help message for scanning every resolved address
+const HELP_ALL_ADDRESSES: &str = "Scan every address a host name resolves to instead of only the first";
+
+/// Long name for the address family filter
+const LONG_FAMILY: &str = "family";
+/// Help message for the address family filter
+const HELP_FAMILY: &str = "Scan only IPv4 or only IPv6 targets; host names keep only their addresses of that family";
+/// Default address family filter
+const DEFAULT_FAMILY: &str = "any";
+
+/// Long name for including network and broadcast addresses
+const LONG_INCLUDE_NETWORK: &str = "include-network";
+/// Help message for including network and broadcast addresses
+const HELP_INCLUDE_NETWORK: &str = "Also scan the network and broadcast addresses of CIDR ranges";
+
+/// Long name for scanning repeated targets more than once
+const LONG_ALLOW_DUPLICATES: &str = "allow-duplicates";
+/// Help message for scanning repeated targets more than once
+const HELP_ALLOW_DUPLICATES: &str = "Scan a host once per time it is listed instead of merging repeated and overlapping targets";
+
+/// Long name for the host count limit
+const LONG_MAX_HOSTS: &str = "max-hosts";
+/// Help message for the host count limit
+const HELP_MAX_HOSTS: &str = "Refuse to scan when the targets expand to more than N hosts after CIDR ranges, target files and \
+host names are expanded and merged";
+/// Default host count limit, the size of the largest single range
+const DEFAULT_MAX_HOSTS: &str = "65536";
+
+/// Long name for scanning the first hosts within the limit
+const LONG_TRUNCATE_HOSTS: &str = "truncate-hosts";
+/// Help message for scanning the first hosts within the limit
+const HELP_TRUNCATE_HOSTS: &str = "Scan the first --max-hosts hosts with a warning instead of refusing larger target lists";
 
 /// Long name for concurrency argument
 const LONG_CONCURRENCY: &str = "concurrency";
 /// Short name for concurrency argument
 const SHORT_CONCURRENCY: char = 'c';
 /// Help message for concurrency argument
-const HELP_CONCURRENCY: &str = "Number of concurrent scans (1-100, default 50)";
+const HELP_CONCURRENCY: &str = "Number of concurrent scans (1-65535, default 50); limited by the open file limit";
 /// Default concurrency value
 const DEFAULT_CONCURRENCY: &str = "50";
+/// Highest accepted concurrency
+const MAX_CONCURRENCY: usize = 65535;
+
+/// Long name for adaptive concurrency
+const LONG_ADAPTIVE: &str = "adaptive";
+/// Help message for adaptive concurrency
+const HELP_ADAPTIVE: &str = "Start with few attempts in flight and grow toward --concurrency while timeouts stay rare, backing off when they spike";
+
+/// Long name for the launch rate limit
+const LONG_RATE: &str = "rate";
+/// Help message for the launch rate limit
+const HELP_RATE: &str = "Start at most this many connection attempts per second (1-1000000), on top of the --concurrency limit";
+/// Highest accepted launch rate
+const MAX_RATE: u32 = 1_000_000;
+
+/// Long name for connect timeout
+const LONG_TIMEOUT: &str = "timeout";
+/// Short name for connect timeout
+const SHORT_TIMEOUT: char = 't';
+/// Alias for connect timeout
+const ALIAS_TIMEOUT: &str = "connect-timeout";
+/// Help message for connect timeout
+const HELP_TIMEOUT: &str = "Connect timeout per port, e.g. 500ms or 2s; bare numbers are milliseconds (max 60s, default 3000). \
+Lower values speed up scans but may miss open ports on slow networks";
+/// Default connect timeout
+const DEFAULT_TIMEOUT: &str = "3000";
+/// Longest accepted connect timeout
+const MAX_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Long name for the total scan time limit
+const LONG_MAX_DURATION: &str = "max-duration";
+/// Help message for the total scan time limit
+const HELP_MAX_DURATION: &str = "Stop starting new attempts after this long, e.g. 60s or 10m, and report what was found so far";
+
+/// Long name for stopping at the first open port
+const LONG_FAIL_FAST: &str = "fail-fast";
+/// Help message for stopping at the first open port
+const HELP_FAIL_FAST: &str = "Stop the scan as soon as one open port is found, abandoning the attempts in flight, and report just that port";
+
+/// Long name for stopping after a number of open ports
+const LONG_MAX_OPEN: &str = "max-open";
+/// Help message for stopping after a number of open ports
+const HELP_MAX_OPEN: &str = "Stop the scan once this many open ports are found, abandoning the attempts in flight, and report \
+what was found so far";
+
+/// Long name for connection retries
+const LONG_RETRIES: &str = "retries";
+/// Help message for connection retries
+const HELP_RETRIES: &str = "Retry a port that did not connect up to N more times, pausing as set by --backoff-mode (0-10, default 0); \
+multiplies the worst-case scan time";
+/// Default number of retries
+const DEFAULT_RETRIES: &str = "0";
+/// Largest accepted number of retries
+const MAX_RETRIES: usize = 10;
+
+/// Long name for the growth of the pause between retries
+const LONG_BACKOFF_MODE: &str = "backoff-mode";
+/// Help message for the growth of the pause between retries
+const HELP_BACKOFF_MODE: &str = "Pause between retries: fixed (100ms each), exponential (100ms, doubled per retry) \
+or full-jitter (random up to the exponential pause, repeatable with --seed)";
+/// Default growth of the pause between retries
+const DEFAULT_BACKOFF_MODE: &str = "exponential";
+/// Long name for the longest pause between retries
+const LONG_BACKOFF_MAX: &str = "backoff-max";
+/// Help message for the longest pause between retries
+const HELP_BACKOFF_MAX: &str = "Longest pause between two retries of a port, e.g. 5s";
+/// Default longest pause between retries
+const DEFAULT_BACKOFF_MAX: &str = "2s";
 
 /// Long name for start port
 const LONG_START_PORT: &str = "start_port";
@@ -60,61 +235,697 @@ const SHORT_END_PORT: char = 'e';
 /// Default end port
 const DEFAULT_END_PORT: &str = "65535";
 
+/// Long name for a single port
+const LONG_PORT: &str = "port";
+/// Help message for a single port
+const HELP_PORT: &str = "Scan only this port (same as --start_port N --end_port N)";
+
+/// Long name for the port list
+const LONG_PORTS: &str = "ports";
+/// Short name for the port list
+const SHORT_PORTS: char = 'p';
+/// Help message for the port list
+const HELP_PORTS: &str = "Ports to scan as a list of ports and ranges, e.g. 22,80,443,8000-8100 (instead of --start_port/--end_port)";
+
+/// Long name for ports left out of the scan
+const LONG_EXCLUDE: &str = "exclude";
+/// Help message for ports left out of the scan
+const HELP_EXCLUDE: &str = "Ports to skip, in the same syntax as --ports, e.g. 135,139,445; ports outside the scanned set are ignored";
+
+/// Long name for the most common ports
+const LONG_TOP_PORTS: &str = "top-ports";
+/// Help message for the most common ports
+const HELP_TOP_PORTS: &str = "Scan only the N most commonly open TCP ports (1-100)";
+
+/// Long name for shuffling the port order
+const LONG_RANDOMIZE: &str = "randomize";
+/// Help message for shuffling the port order
+const HELP_RANDOMIZE: &str = "Scan the ports in random order instead of ascending";
+
+/// Long name for descending port order
+const LONG_DESCENDING: &str = "descending";
+/// Help message for descending port order
+const HELP_DESCENDING: &str = "Scan the ports from highest to lowest instead of ascending";
+
+/// Long name for interleaving the hosts
+const LONG_INTERLEAVE: &str = "interleave";
+/// Help message for interleaving the hosts
+const HELP_INTERLEAVE: &str = "Take one port from each host in turn instead of finishing each host before the next, \
+so a slow host does not hold up the others";
+
+/// Long name for the shuffle seed
+const LONG_SEED: &str = "seed";
+/// Help message for the shuffle seed
+const HELP_SEED: &str = "Seed for --randomize and --backoff-mode full-jitter, to repeat the same order and retry pauses (random by default)";
+
+/// Long name for chunk size
+const LONG_CHUNK_SIZE: &str = "chunk-size";
+/// Help message for chunk size
+const HELP_CHUNK_SIZE: &str = "Scan the range in batches of N ports, pausing between batches";
+
+/// Long name for chunk pause
+const LONG_CHUNK_PAUSE: &str = "chunk-pause";
+/// Help message for chunk pause
+const HELP_CHUNK_PAUSE: &str = "Pause between batches, e.g. 500ms or 2s; bare numbers are milliseconds (requires --chunk-size, default 1s)";
+/// Default chunk pause
+const DEFAULT_CHUNK_PAUSE: &str = "1s";
+
+/// Long name for the resume file
+const LONG_RESUME: &str = "resume";
+/// Help message for the resume file
+const HELP_RESUME: &str = "Record finished ports in FILE and skip the ones already recorded there (see README)";
+
+/// Long name for SQLite export
+const LONG_SQLITE: &str = "sqlite";
+/// Help message for SQLite export
+const HELP_SQLITE: &str = "Append results to a SQLite database (tables are created if absent)";
+/// Alias for SQLite export
+const ALIAS_SQLITE: &str = "db";
+
+/// Long name for comparing with the previous run
+const LONG_DIFF: &str = "diff";
+/// Help message for comparing with the previous run
+const HELP_DIFF: &str = "With --sqlite, show which ports opened or closed on each host since its previous recorded scan";
+
+/// Long name for protocol identification
+const LONG_IDENTIFY: &str = "identify";
+/// Help message for protocol identification
+const HELP_IDENTIFY: &str = "Probe open ports to detect the protocol they speak (http, ssh, tls, ...)";
+
+/// Long name for HTTP fingerprinting
+const LONG_PROBE_HTTP: &str = "probe-http";
+/// Help message for HTTP fingerprinting
+const HELP_PROBE_HTTP: &str = "Request / from open ports and show the HTTP Server header and page title";
+
+/// Long name for the TLS handshake probe
+const LONG_TLS_PROBE: &str = "tls-probe";
+/// Help message for the TLS handshake probe
+const HELP_TLS_PROBE: &str = "Attempt a TLS handshake with open ports and show the TLS version and certificate common name";
+
+/// Long name for banner grabbing
+const LONG_BANNER: &str = "banner";
+/// Help message for banner grabbing
+const HELP_BANNER: &str = "Read the greeting that open ports send on connect (e.g. SSH version, SMTP greeting)";
+
+/// Long name for the order of the follow-up probes
+const LONG_PROBE_ORDER: &str = "probe-order";
+/// Help message for the order of the follow-up probes
+const HELP_PROBE_ORDER: &str = "Order of the enabled probes on each open port; once one shows what the port speaks, \
+probes for other protocols are skipped (e.g. no plaintext banner after a TLS handshake)";
+/// Default order of the follow-up probes
+const DEFAULT_PROBE_ORDER: &str = "tls,http,identify,banner";
+
+/// Long name for the banner size limit
+const LONG_BANNER_BYTES: &str = "banner-bytes";
+/// Help message for the banner size limit
+const HELP_BANNER_BYTES: &str = "Read at most this many bytes of a banner or held connection (default 1024)";
+/// Default banner size limit
+const DEFAULT_BANNER_BYTES: &str = "1024";
+/// Largest accepted banner size limit
+const MAX_BANNER_BYTES: usize = 65536;
+
+/// Long name for the shortest banner kept
+const LONG_MIN_RESPONSE_BYTES: &str = "min-response-bytes";
+/// Help message for the shortest banner kept
+const HELP_MIN_RESPONSE_BYTES: &str = "Treat banners shorter than N bytes (keepalives, stray bytes) as no banner; \
+at most --banner-bytes";
+
+/// Long name for the read timeout after connecting
+const LONG_READ_TIMEOUT: &str = "read-timeout";
+/// Alias for the read timeout, from when only banners had one
+const ALIAS_READ_TIMEOUT: &str = "banner-timeout";
+/// Help message for the read timeout after connecting
+const HELP_READ_TIMEOUT: &str = "How long --banner, --identify, --probe-http and --tls-probe wait for a service to answer \
+once connected, e.g. 500ms or 5s (default 2000ms, at most 30s)";
+/// Default read timeout
+const DEFAULT_READ_TIMEOUT: &str = "2000ms";
+/// Longest accepted read timeout
+const MAX_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Long name for holding open connections
+const LONG_WAIT_BEFORE_CLOSE: &str = "wait-before-close";
+/// Help message for holding open connections
+const HELP_WAIT_BEFORE_CLOSE: &str = "Keep each open TCP connection for this long (at most 30s) and show what the service sends meanwhile";
+/// Longest accepted hold
+const MAX_WAIT_BEFORE_CLOSE: Duration = Duration::from_secs(30);
+
+/// Long name for disabling service names
+const LONG_NO_SERVICE_NAMES: &str = "no-service-names";
+/// Help message for disabling service names
+const HELP_NO_SERVICE_NAMES: &str = "List open ports as bare numbers, without `80/tcp open http` style service names";
+
+/// Long name for always exiting with 0 after a scan
+const LONG_EXIT_ZERO: &str = "exit-zero";
+/// Help message for always exiting with 0 after a scan
+const HELP_EXIT_ZERO: &str = "Exit with 0 even when no open ports are found";
+
+/// Long name for leaving hosts without open ports out of the report
+const LONG_OPEN_ONLY: &str = "open-only";
+/// Help message for leaving hosts without open ports out of the report
+const HELP_OPEN_ONLY: &str = "Leave hosts without open ports out of the report";
+
+/// Long name for hiding the progress bar
+const LONG_QUIET: &str = "quiet";
+/// Short name for hiding the progress bar
+const SHORT_QUIET: char = 'q';
+/// Help message for hiding the progress bar
+const HELP_QUIET: &str = "Hide the progress bar; only the report and errors are printed";
+
+/// Long name for forcing the progress bar
+const LONG_PROGRESS: &str = "progress";
+/// Help message for forcing the progress bar
+const HELP_PROGRESS: &str = "Show the progress bar even when stdout or stderr is not a terminal";
+/// Alias for forcing the progress bar
+const ALIAS_PROGRESS: &str = "force-progress";
+/// Redraws per second of a forced progress bar, as indicatif uses on a terminal
+const FORCED_PROGRESS_HZ: u8 = 20;
+
+/// Long name for the progress bar preset
+const LONG_PROGRESS_STYLE: &str = "progress-style";
+/// Help message for the progress bar preset
+const HELP_PROGRESS_STYLE: &str = "Look of the progress bar: bar, spinner (no determinate bar) or plain (counts and ETA only)";
+/// Default progress bar preset
+const DEFAULT_PROGRESS_STYLE: &str = "bar";
+/// Spinner redraw interval, so it keeps turning while attempts hang
+const SPINNER_TICK: Duration = Duration::from_millis(100);
+
+/// Long name for logging every result
+const LONG_VERBOSE: &str = "verbose";
+/// Short name for logging every result
+const SHORT_VERBOSE: char = 'v';
+/// Help message for logging every result
+const HELP_VERBOSE: &str = "Log each port's result (open, closed, timeout) to stderr as it happens; very chatty on large scans";
+
+/// Long name for streaming open ports
+const LONG_STREAM: &str = "stream";
+/// Help message for streaming open ports
+const HELP_STREAM: &str = "Print each open port to stdout as soon as it is found, before the final report";
+
+/// Long name for the simulated latency test hook
+const LONG_SIMULATE_LATENCY: &str = "simulate-latency";
+
+
+/// Long name for the result socket
+const LONG_EMIT_SOCKET: &str = "emit-socket";
+/// Help message for the result socket
+const HELP_EMIT_SOCKET: &str = "Stream open ports as JSON lines to a Unix domain socket as they are found";
+
+/// Long name for result-count flushing
+const LONG_FLUSH_EVERY: &str = "flush-every";
+/// Help message for result-count flushing
+const HELP_FLUSH_EVERY: &str = "Flush streamed results after this many lines (default 16)";
+/// Default number of streamed results per flush
+const DEFAULT_FLUSH_EVERY: &str = "16";
+
+/// Long name for time-based flushing
+const LONG_FLUSH_INTERVAL: &str = "flush-interval";
+/// Help message for time-based flushing
+const HELP_FLUSH_INTERVAL: &str = "Flush streamed results at least this often, e.g. 250ms or 1s; bare numbers are milliseconds (default 1s)";
+/// Default flush interval
+const DEFAULT_FLUSH_INTERVAL: &str = "1s";
+
+/// Long name for the coalescing threshold
+const LONG_COALESCE_THRESHOLD: &str = "coalesce-threshold";
+/// Help message for the coalescing threshold
+const HELP_COALESCE_THRESHOLD: &str = "Collapse runs of at least N consecutive open ports into one range line";
+
+/// Long name for closed range reporting
+const LONG_REPORT_CLOSED_RANGES: &str = "report-closed-ranges";
+/// Help message for closed range reporting
+const HELP_REPORT_CLOSED_RANGES: &str = "Also list the closed and filtered ports as coalesced ranges";
+
+/// Long name for listing every port
+const LONG_SHOW_ALL: &str = "show-all";
+/// Help message for listing every port
+const HELP_SHOW_ALL: &str = "List every scanned port with its status (open, closed, filtered) and the reason, in text output";
+
+/// Long name for printing only the open port count
+const LONG_COUNT_ONLY: &str = "count-only";
+/// Help message for printing only the open port count
+const HELP_COUNT_ONLY: &str = "Print only the number of open ports to stdout instead of the report, for shell scripts";
+
+/// Long name for the machine-readable summary
+const LONG_SUMMARY_LINE: &str = "summary-line";
+/// Help message for the machine-readable summary
+const HELP_SUMMARY_LINE: &str = "Print a final `SUMMARY scanned=N open=N closed=N filtered=N elapsed_ms=N` line to stderr, \
+whatever the format";
+
+/// Long name for RTT-based timeouts
+const LONG_TIMEOUT_BY_RTT: &str = "timeout-by-rtt";
+/// Help message for RTT-based timeouts
+const HELP_TIMEOUT_BY_RTT: &str = "Calibrate the connect timeout from the target's measured round-trip time";
+
+/// Long name for the confirmation threshold
+const LONG_CONFIRM_THRESHOLD: &str = "confirm-threshold";
+/// Help message for the confirmation threshold
+const HELP_CONFIRM_THRESHOLD: &str = "Ask for confirmation above this many connection attempts (default 100000)";
+/// Default confirmation threshold
+const DEFAULT_CONFIRM_THRESHOLD: &str = "100000";
+
+/// Long name for skipping the confirmation
+const LONG_YES: &str = "yes";
+/// Short name for skipping the confirmation
+const SHORT_YES: char = 'y';
+/// Help message for skipping the confirmation
+const HELP_YES: &str = "Run scans above --confirm-threshold without asking";
+
+/// Long name for allowing public targets
+const LONG_ALLOW_PUBLIC: &str = "i-know-what-im-doing";
+/// Help message for allowing public targets
+const HELP_ALLOW_PUBLIC: &str = "Scan public (non-private, non-local) addresses, which are refused otherwise; \
+only for hosts you are authorized to test";
+
+/// Long name for the report file
+const LONG_OUTPUT: &str = "output";
+/// Short name for the report file
+const SHORT_OUTPUT: char = 'o';
+/// Help message for the report file
+const HELP_OUTPUT: &str = "Write the final report to this file instead of stdout (in the --format format)";
+
+/// Long name for the output format
+const LONG_FORMAT: &str = "format";
+/// Short name for the output format
+const SHORT_FORMAT: char = 'f';
+/// Help message for the output format
+const HELP_FORMAT: &str = "Output format of the final report";
+/// Default output format
+const DEFAULT_FORMAT: &str = "text";
+
+/// Long name for report colors
+const LONG_COLOR: &str = "color";
+/// Help message for report colors
+const HELP_COLOR: &str = "Color the text report: auto (terminal and no NO_COLOR), always or never";
+/// Default report colors
+const DEFAULT_COLOR: &str = "auto";
+
+/// Long name for the protocol
+const LONG_PROTOCOL: &str = "protocol";
+/// Help message for the protocol
+const HELP_PROTOCOL: &str = "Transport protocol to scan";
+/// Default protocol
+const DEFAULT_PROTOCOL: &str = "tcp";
+
+/// Long name for the timeout ramp
+const LONG_TIMEOUT_RAMP: &str = "connect-timeout-ramp";
+/// Help message for the timeout ramp
+const HELP_TIMEOUT_RAMP: &str = "Experimental: start with a short connect timeout and lengthen it as the target proves slower";
+
+/// Long name for the progress feed descriptor
+const LONG_PROGRESS_FD: &str = "progress-fd";
+/// Help message for the progress feed descriptor
+const HELP_PROGRESS_FD: &str = "Write JSON progress events to this open file descriptor (Unix)";
+
+/// Long name for the gateway
+const LONG_VIA: &str = "via";
+/// Help message for the gateway
+const HELP_VIA: &str = "Send scan traffic from the local address that faces this gateway (see README)";
+
+/// Long name for the source address
+const LONG_SOURCE_IP: &str = "source-ip";
+/// Help message for the source address
+const HELP_SOURCE_IP: &str = "Bind scan connections to this local address (same family as the targets)";
+
+/// Long name for the SOCKS5 proxy
+const LONG_PROXY: &str = "proxy";
+/// Help message for the SOCKS5 proxy
+const HELP_PROXY: &str = "Connect through this SOCKS5 proxy, given as socks5://[user:password@]host:port (TCP only)";
+
+/// Long name for printing the reproduction command
+const LONG_PRINT_REPRODUCE: &str = "print-reproduce";
+/// Help message for printing the reproduction command
+const HELP_PRINT_REPRODUCE: &str = "Print the normalized command line (all defaults explicit) that reproduces this scan";
+
+/// Long name for listing the scan without connecting
+const LONG_DRY_RUN: &str = "dry-run";
+/// Help message for listing the scan without connecting
+const HELP_DRY_RUN: &str = "Expand targets and ports, print what would be scanned and exit without connecting";
+/// Number of host/port pairs shown by --dry-run
+const DRY_RUN_SAMPLE: usize = 10;
+
+/// Long name for tarpit detection
+const LONG_TIMEOUT_AS_OPEN: &str = "timeout-as-open";
+/// Help message for tarpit detection
+const HELP_TIMEOUT_AS_OPEN: &str = "Report ports whose connect consistently hangs as possibly tarpitted";
+
+/// Long name for the second pass over timed-out ports
+const LONG_VERIFY: &str = "verify";
+/// Help message for the second pass over timed-out ports
+const HELP_VERIFY: &str = "After the scan, try the ports that timed out again with a longer timeout and at least one retry, \
+and report those that answer";
+/// Long name for the timeout of the verify pass
+const LONG_VERIFY_TIMEOUT: &str = "verify-timeout";
+/// Help message for the timeout of the verify pass
+const HELP_VERIFY_TIMEOUT: &str = "Connect timeout of the --verify pass, e.g. 5s [default: three times --timeout]";
+/// The verify pass waits this many times the scan's timeout by default
+const VERIFY_TIMEOUT_FACTOR: u32 = 3;
+
+
+/// Exit code when the scan found no open ports
+const EXIT_NO_OPEN_PORTS: i32 = 1;
+/// Exit code for invalid arguments and other errors, as clap uses for usage errors
+const EXIT_ERROR: i32 = 2;
+/// Exit code after Ctrl-C, as for a shell command killed by SIGINT
+const EXIT_INTERRUPTED: i32 = 130;
+
 /// Minimum valid TCP port
 const MIN_PORT: u16 = 1;
 /// Maximum valid TCP port
 const MAX_PORT: u16 = 65535;
 
-/// Buffer size for the mpsc channel
-const CHANNEL_BUFFER_SIZE: usize = 250;
 
 /* -------------------------
-   Asynchronous scan function
+   Post-scan steps
    ------------------------- */
 
-/// Attempts to connect to a given IP and port asynchronously.
-/// 
-/// If the connection succeeds, the port is sent through the mpsc channel and
-/// the progress bar is incremented.
-///
-/// # Arguments
+/// Follow-up probes of each open TCP port
+struct Probes {
+    /// The enabled probes, in `--probe-order`
+    order: Vec<Probe>,
+    /// The banner was read on the scan's own connection, as no other probe
+    /// comes before it
+    banner_in_scan: bool,
+    /// Most banner bytes read
+    banner_bytes: usize,
+    /// Shorter banners count as none (`--min-response-bytes`)
+    min_banner_bytes: usize,
+    /// Local address to connect from, if pinned
+    source: Option<IpAddr>,
+    /// How long each probe waits for an answer once connected
+    read_timeout: Duration,
+    /// Ports probed at the same time
+    concurrency: usize,
+}
+
+impl Probes {
+    /// Whether any probe is left to run after the scan
+    fn any(&self) -> bool {
+        self.order.iter().any(|&probe| probe != Probe::Banner || !self.banner_in_scan)
+    }
+}
+
+/// What the follow-up probes learned about one open port
+#[derive(Default)]
+struct Probed {
+    /// Protocol found by `--identify`
+    detected: Option<&'static str>,
+    /// Greeting read by `--banner` after other probes
+    banner: Option<String>,
+    /// Server header and page title found by `--probe-http`
+    http: Option<http_probe::HttpInfo>,
+    /// Handshake result of `--tls-probe`
+    tls: Option<tls_probe::TlsInfo>,
+}
+
+/// Runs the enabled probes on `port` of `host` in order, skipping those
+/// an earlier answer made pointless (see `probe_order`). `scan_banner` is
+/// the greeting the scan itself read, if any.
+async fn probe_port(host: &HostTarget, port: u16, scan_banner: Option<String>, probes: &Probes) -> Probed {
+    let target = socket_addr(host.ip, port, host.scope_id);
+    let (connect_timeout, read_timeout) = (host.connect_timeout, probes.read_timeout);
+    let mut probed = Probed::default();
+    let mut kind: Option<Kind> = None;
+    for &probe in &probes.order {
+        if kind.is_some_and(|kind| kind.skips(probe)) {
+            debug!("{target}: skipping the {} probe", probe.name());
+            continue;
+        }
+        match probe {
+            Probe::Tls => {
+                let tls = tls_probe::probe(target, probes.source, connect_timeout, read_timeout).await;
+                if tls.handshake {
+                    kind = Some(Kind::Tls);
+                }
+                probed.tls = Some(tls);
+            }
+            Probe::Http => {
+                probed.http = http_probe::probe(target, probes.source, connect_timeout, read_timeout).await;
+                if probed.http.is_some() {
+                    kind = Some(Kind::Http);
+                }
+            }
+            Probe::Banner => {
+                probed.banner = if probes.banner_in_scan {
+                    scan_banner.clone()
+                } else {
+                    grab_banner(target, probes.source, connect_timeout, probes.banner_bytes, read_timeout).await
+                }
+                .filter(|banner| banner.len() >= probes.min_banner_bytes);
+                if probed.banner.is_some() {
+                    kind = Some(Kind::Other);
+                }
+            }
+            Probe::Identify => {
+                // What earlier probes showed, before sending probes of its own
+                probed.detected = kind.and_then(Kind::detected).or_else(|| probed.banner.as_deref().and_then(|b| identify::classify(b.as_bytes())));
+                if probed.detected.is_none() {
+                    probed.detected = identify::identify(target, probes.source, connect_timeout, read_timeout).await;
+                }
+                kind = kind.or(probed.detected.map(Kind::of_detected));
+            }
+        }
+    }
+    probed
+}
+
+/// Runs the enabled probes on `host`'s `open` ports; one entry per port, in
+/// the same order.
+async fn probe_open_ports(host: &HostTarget, scanned: &ScanResult, probes: &Probes) -> Vec<Probed> {
+    stream::iter(scanned.open.iter().copied())
+        .map(|port| probe_port(host, port, scanned.banners.get(&port).cloned(), probes))
+        .buffered(probes.concurrency)
+        .collect()
+        .await
+}
+
+/// `--verify`: tries `scanned`'s timed-out ports on `host` again with the
+/// longer `verify_timeout`, merges in those that answered and says how many
+/// changed. With JSON lines on stdout, the newly open ports are printed too.
+async fn verify_pass(
+    config: &ScanConfig,
+    host: &HostTarget,
+    scanned: &mut ScanResult,
+    verify_timeout: Duration,
+    retries: usize,
+    pb: &ProgressBar,
+    stream_jsonl: bool,
+) {
+    let ip = host.ip;
+    let silent = report::silent_label(config.protocol);
+    let total = scanned.timed_out.len();
+    pb.set_message(format!("verifying {total} {silent} ports on {ip}"));
+    let verified = verify_timeouts(config, host, &scanned.timed_out, verify_timeout, retries).await;
+    let closed = total - verified.open.len() - verified.timed_out.len();
+    pb.suspend(|| {
+        eprintln!(
+            "Verify pass ({ip}): {} of {total} {silent} ports reclassified as open, {closed} as closed",
+            verified.open.len()
+        );
+        if stream_jsonl {
+            for &port in &verified.open {
+                println!("{}", json_line(ip, port));
+            }
+        }
+    });
+    scanned.open.extend(&verified.open);
+    scanned.open.sort_unstable();
+    scanned.latencies.extend(verified.latencies);
+    scanned.banners.extend(verified.banners);
+    scanned.timed_out = verified.timed_out;
+}
+
+/// Appends this run to the `--sqlite` database and, with `diff`, prints on
+/// stderr how each host changed since its previous recorded scan.
+fn record_and_diff(conn: &mut rusqlite::Connection, hosts: &[HostReport], ports: &[u16], started_at: SystemTime, diff: bool) {
+    let results: Vec<db::HostRecord> = hosts
+        .iter()
+        .map(|host| db::HostRecord {
+            ip: host.ip,
+            ports: host
+                .attempted
+                .iter()
+                .map(|&port| {
+                    let status = if host.open.binary_search_by_key(&port, |entry| entry.port).is_ok() {
+                        db::Status::Open
+                    } else if host.timed_out.binary_search(&port).is_ok() {
+                        db::Status::Timeout
+                    } else {
+                        db::Status::Closed
+                    };
+                    (port, status)
+                })
+                .collect(),
+        })
+        .collect();
+    match db::record_scan(conn, started_at, ports[0], ports[ports.len() - 1], &results) {
+        Ok(scan_id) if diff => {
+            for host in hosts {
+                match db::previous_scan(conn, host.ip, scan_id) {
+                    Ok(previous) => eprintln!("{}", diff_line(host, previous.as_ref(), started_at)),
+                    Err(e) => eprintln!("Warning: cannot read the previous scan of {} from SQLite: {e}", host.ip),
+                }
+            }
+        }
+        Ok(_) => {}
+        Err(e) => eprintln!("Error: failed to write results to SQLite: {e}"),
+    }
+}
+
+/// One `--diff` line for `host`: the ports that opened or closed since
+/// `previous`, among those both runs covered.
 ///
-/// * `tx` - Channel sender to report open ports
-/// * `port` - Port number to test
-/// * `addr` - Target IP address
-/// * `pb` - Shared progress bar
-async fn scan(tx: mpsc::Sender<u16>, port: u16, addr: IpAddr, pb: Arc<ProgressBar>) {
-    // Timeout of 3 seconds for the connection attempt
-    let result = timeout(Duration::from_secs(3), TcpStream::connect((addr, port))).await;
-
-    // Ok(Ok(_)) = connection succeeded before timeout
-    if let Ok(Ok(_)) = result {
-        // Send the open port to the channel (ignore failure)
-        let _ = tx.send(port).await;
-    }
-
-    // Increment the progress bar regardless of success or failure
-    pb.inc(1);
+/// Only ports that both runs attempted are compared, so a narrower or
+/// interrupted scan does not report the ports it skipped as closed, nor one
+/// after it the ports the earlier run skipped as newly open.
+fn diff_line(host: &HostReport, previous: Option<&db::PreviousScan>, started_at: SystemTime) -> String {
+    let ip = host.ip;
+    let Some(previous) = previous else {
+        return format!("{ip}: first scan recorded for this host");
+    };
+    let covered = |port: &u16| previous.attempted.binary_search(port).is_ok() && host.attempted.binary_search(port).is_ok();
+    let open_now: Vec<u16> = host.open.iter().map(|entry| entry.port).filter(covered).collect();
+    let open_before: Vec<u16> = previous.open.iter().copied().filter(covered).collect();
+    let opened: Vec<u16> = open_now.iter().copied().filter(|port| open_before.binary_search(port).is_err()).collect();
+    let closed: Vec<u16> = open_before.iter().copied().filter(|port| open_now.binary_search(port).is_err()).collect();
+
+    let then = SystemTime::UNIX_EPOCH + Duration::from_secs(previous.started_at.max(0) as u64);
+    let age = started_at.duration_since(then).map_or_else(|_| String::from("earlier"), |age| format!("{} ago", guard::format_estimate(age)));
+    let since = format!("since scan #{} ({age})", previous.id);
+    match (opened.is_empty(), closed.is_empty()) {
+        (true, true) => format!("{ip}: no changes {since}"),
+        _ => {
+            let mut changes = Vec::new();
+            if !opened.is_empty() {
+                changes.push(format!("newly open {}", ranges::format_ranges(&ranges::coalesce(&opened))));
+            }
+            if !closed.is_empty() {
+                changes.push(format!("no longer open {}", ranges::format_ranges(&ranges::coalesce(&closed))));
+            }
+            format!("{ip}: {since}: {}", changes.join("; "))
+        }
+    }
 }
 
-/* -------------------------
-   Main function
-   ------------------------- */
+/// Whether flag `id` was set explicitly, on the command line or through
+/// its environment variable, rather than left at its default
+fn explicit(matches: &ArgMatches, id: &str) -> bool {
+    matches!(matches.value_source(id), Some(ValueSource::CommandLine | ValueSource::EnvVariable))
+}
 
-/// Main asynchronous entry point using Tokio runtime
-#[tokio::main]
-async fn main() {
-    // Parse command-line arguments with clap
-    let matches = Command::new(APP_NAME)
+/// Returns the plan's value for a setting unless flag `id` was set
+/// explicitly (which always wins).
+fn from_plan<T>(matches: &ArgMatches, id: &str, plan_value: Option<T>) -> Option<T> {
+    if explicit(matches, id) { None } else { plan_value }
+}
+
+/// Environment variable for flag `id`: `PORT_SNIFFER_` and the long name in
+/// upper case with `-` as `_`
+fn env_name(id: &str) -> String {
+    format!("{ENV_PREFIX}{}", id.to_uppercase().replace('-', "_"))
+}
+
+/// Command-line interface: every flag, its validation and its environment
+/// variable
+fn command() -> Command {
+    Command::new(APP_NAME)
         .version(VERSION)
         .author(AUTHOR)
         .about(ABOUT)
+        .subcommand(Command::new(selftest::NAME).about(selftest::ABOUT))
+        .subcommand_negates_reqs(true) // `selftest` does not need --ip
         .arg(
             Arg::new(LONG_IP)
                 .long(LONG_IP)
                 .help(HELP_IP)
-                .required(true) // IP is mandatory
-                .value_parser(value_parser!(IpAddr)), // Auto-parse as IP
+                .required_unless_present_any([LONG_PLAN, LONG_TARGET_FILE, LONG_TARGET]) // IP is mandatory unless a plan, list or --target names targets
+                .action(ArgAction::Append) // `--ip A --ip B`
+                .value_delimiter(',') // `--ip A,B`
+                .value_parser(cidr::parse), // Address, CIDR range or host name
+        )
+        .arg(
+            Arg::new(LONG_TARGET_FILE)
+                .long(LONG_TARGET_FILE)
+                .value_name("FILE")
+                .help(HELP_TARGET_FILE)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new(LONG_TARGET)
+                .long(LONG_TARGET)
+                .value_name("ADDR:PORT")
+                .help(HELP_TARGET)
+                .conflicts_with_all([
+                    LONG_IP,
+                    LONG_TARGET_FILE,
+                    LONG_START_PORT,
+                    LONG_END_PORT,
+                    LONG_PORT,
+                    LONG_PORTS,
+                    LONG_TOP_PORTS,
+                    LONG_EXCLUDE,
+                    LONG_OPEN_ONLY,
+                ])
+                .value_parser(cidr::parse_endpoint), // Literal address and port
+        )
+        .arg(
+            Arg::new(LONG_ALL_ADDRESSES)
+                .long(LONG_ALL_ADDRESSES)
+                .help(HELP_ALL_ADDRESSES)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_FAMILY)
+                .long(LONG_FAMILY)
+                .help(HELP_FAMILY)
+                .default_value(DEFAULT_FAMILY)
+                .value_parser(value_parser!(Family)),
+        )
+        .arg(
+            Arg::new(LONG_INCLUDE_NETWORK)
+                .long(LONG_INCLUDE_NETWORK)
+                .help(HELP_INCLUDE_NETWORK)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_ALLOW_DUPLICATES)
+                .long(LONG_ALLOW_DUPLICATES)
+                .help(HELP_ALLOW_DUPLICATES)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_MAX_HOSTS)
+                .long(LONG_MAX_HOSTS)
+                .value_name("N")
+                .help(HELP_MAX_HOSTS)
+                .default_value(DEFAULT_MAX_HOSTS)
+                .value_parser(|x: &str| {
+                    // Validate the host count limit
+                    let val: usize = x.parse().map_err(|_| format!("`{x}` is not a number"))?;
+                    if val == 0 {
+                        Err(String::from("Host limit must be at least 1"))
+                    } else {
+                        Ok(val)
+                    }
+                }),
+        )
+        .arg(
+            Arg::new(LONG_TRUNCATE_HOSTS)
+                .long(LONG_TRUNCATE_HOSTS)
+                .help(HELP_TRUNCATE_HOSTS)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_CONFIG)
+                .long(LONG_CONFIG)
+                .value_name("FILE")
+                .help(HELP_CONFIG)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new(LONG_PLAN)
+                .long(LONG_PLAN)
+                .value_name("PLAN.json")
+                .help(HELP_PLAN)
+                .value_parser(value_parser!(PathBuf)),
         )
         .arg(
             Arg::new(LONG_CONCURRENCY)
@@ -125,10 +936,112 @@ async fn main() {
                 .value_parser(|x: &str| {
                     // Validate number and range
                     let val: usize = x.parse().map_err(|_| format!("`{x}` is not a number"))?;
-                    if (1..=100).contains(&val) {
+                    if (1..=MAX_CONCURRENCY).contains(&val) {
+                        Ok(val)
+                    } else {
+                        Err(format!("Concurrency must be between 1 and {MAX_CONCURRENCY}"))
+                    }
+                }),
+        )
+        .arg(
+            Arg::new(LONG_ADAPTIVE)
+                .long(LONG_ADAPTIVE)
+                .help(HELP_ADAPTIVE)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_RATE)
+                .long(LONG_RATE)
+                .value_name("PPS")
+                .help(HELP_RATE)
+                .value_parser(|x: &str| {
+                    // Validate rate range
+                    let val: u32 = x.parse().map_err(|_| format!("`{x}` is not a number"))?;
+                    if (1..=MAX_RATE).contains(&val) {
+                        Ok(val)
+                    } else {
+                        Err(format!("Rate must be between 1 and {MAX_RATE}"))
+                    }
+                }),
+        )
+        .arg(
+            Arg::new(LONG_TIMEOUT)
+                .short(SHORT_TIMEOUT)
+                .long(LONG_TIMEOUT)
+                .visible_alias(ALIAS_TIMEOUT)
+                .help(HELP_TIMEOUT)
+                .default_value(DEFAULT_TIMEOUT)
+                .value_parser(|x: &str| {
+                    // Validate timeout range
+                    let val = duration::parse_non_zero(x)?;
+                    if val > MAX_TIMEOUT {
+                        Err(String::from("Timeout must be at most 60000 milliseconds"))
+                    } else {
+                        Ok(val)
+                    }
+                }),
+        )
+        .arg(
+            Arg::new(LONG_MAX_DURATION)
+                .long(LONG_MAX_DURATION)
+                .value_name("DURATION")
+                .help(HELP_MAX_DURATION)
+                .value_parser(duration::parse_non_zero),
+        )
+        .arg(
+            Arg::new(LONG_FAIL_FAST)
+                .long(LONG_FAIL_FAST)
+                .help(HELP_FAIL_FAST)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_MAX_OPEN)
+                .long(LONG_MAX_OPEN)
+                .value_name("N")
+                .help(HELP_MAX_OPEN)
+                .conflicts_with(LONG_FAIL_FAST)
+                .value_parser(|x: &str| {
+                    // Validate a positive count
+                    let val: usize = x.parse().map_err(|_| format!("`{x}` is not a number"))?;
+                    if val == 0 { Err("Must be at least 1".to_string()) } else { Ok(val) }
+                }),
+        )
+        .arg(
+            Arg::new(LONG_RETRIES)
+                .long(LONG_RETRIES)
+                .value_name("N")
+                .help(HELP_RETRIES)
+                .default_value(DEFAULT_RETRIES)
+                .value_parser(|x: &str| {
+                    // Validate retry count range
+                    let val: usize = x.parse().map_err(|_| format!("`{x}` is not a number"))?;
+                    if val <= MAX_RETRIES {
                         Ok(val)
                     } else {
-                        Err(String::from("Concurrency must be between 1 and 100"))
+                        Err(format!("Retries must be at most {MAX_RETRIES}"))
+                    }
+                }),
+        )
+        .arg(
+            Arg::new(LONG_BACKOFF_MODE)
+                .long(LONG_BACKOFF_MODE)
+                .help(HELP_BACKOFF_MODE)
+                .default_value(DEFAULT_BACKOFF_MODE)
+                .value_parser(value_parser!(BackoffMode)),
+        )
+        .arg(
+            Arg::new(LONG_BACKOFF_MAX)
+                .long(LONG_BACKOFF_MAX)
+                .value_name("DURATION")
+                .help(HELP_BACKOFF_MAX)
+                .default_value(DEFAULT_BACKOFF_MAX)
+                .value_parser(|x: &str| {
+                    // Validate the pause against the timeout range
+                    let val = duration::parse(x)?;
+                    if val > MAX_TIMEOUT {
+                        Err(String::from("Backoff must be at most 60000 milliseconds"))
+                    } else {
+                        Ok(val)
                     }
                 }),
         )
@@ -140,7 +1053,7 @@ async fn main() {
                 .value_parser(|x: &str| {
                     // Validate start port
                     let val: u16 = x.parse().map_err(|_| format!("`{x}` is not a valid port"))?;
-                    if val < MIN_PORT || val > MAX_PORT {
+                    if !(MIN_PORT..=MAX_PORT).contains(&val) {
                         Err(format!("Port must be between {MIN_PORT} and {MAX_PORT}"))
                     } else {
                         Ok(val)
@@ -155,79 +1068,1628 @@ async fn main() {
                 .value_parser(|x: &str| {
                     // Validate end port
                     let val: u16 = x.parse().map_err(|_| format!("`{x}` is not a valid port"))?;
-                    if val < MIN_PORT || val > MAX_PORT {
+                    if !(MIN_PORT..=MAX_PORT).contains(&val) {
                         Err(format!("Port must be between {MIN_PORT} and {MAX_PORT}"))
                     } else {
                         Ok(val)
                     }
                 }),
         )
-        .get_matches();
-
-    // Extract values from CLI arguments
-    let ip = matches.get_one::<IpAddr>(LONG_IP).copied().expect("Required by clap");
-    let concurrency = matches.get_one::<usize>(LONG_CONCURRENCY).copied().unwrap();
-    let start_port = matches.get_one::<u16>(LONG_START_PORT).copied().expect("Default ensured by clap");
-    let end_port = matches.get_one::<u16>(LONG_END_PORT).copied().expect("Default ensured by clap");
-
-    // Ensure start_port <= end_port
-    if start_port > end_port {
-        eprintln!("Error: start_port ({start_port}) cannot be greater than end_port ({end_port})");
-        std::process::exit(1);
-    }
-
-    // Total number of ports to scan
-    let total_ports: u64 = (end_port - start_port + 1).into();
-
-    // Create a shared progress bar
-    let pb = Arc::new({
-        let pb = ProgressBar::new(total_ports);
-        let style = ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] {bar:40.red/cyan} {pos}/{len} ({eta})")
-            .unwrap_or_else(|_| ProgressStyle::default_bar()) // fallback if template fails
-            .progress_chars("=>-");
-        pb.set_style(style);
-        pb
-    });
-
-    // Create channel for collecting open ports
-    let (tx, mut rx) = mpsc::channel(CHANNEL_BUFFER_SIZE);
-
-    // Create a stream of ports to scan
-    let ports = tokio_stream::iter(start_port..=end_port);
-
-    // Scan ports concurrently with the specified limit
-    ports
-        .for_each_concurrent(concurrency, |port| {
-            let tx = tx.clone();
-            let pb = pb.clone();
-            async move {
-                scan(tx, port, ip, pb).await;
-            }
-        })
-        .await;
-
-    drop(tx); // Close the channel when all tasks finish
-
-    // Collect open ports from the channel
-    let mut open_ports = vec![];
-    while let Some(port) = rx.recv().await {
-        open_ports.push(port);
-    }
-
-    // Finish the progress bar with a message
-    pb.finish_with_message("Scan Completed Successfully!");
-
-    println!();
-
-    // Sort and display open ports
-    open_ports.sort();
-    if open_ports.is_empty() {
-        println!("No open ports found.");
-    } else {
-        println!("Open ports: ");
-        for p in open_ports {
-            println!("{p}");
-        }
+        .arg(
+            Arg::new(LONG_PORT)
+                .long(LONG_PORT)
+                .value_name("PORT")
+                .help(HELP_PORT)
+                .conflicts_with_all([LONG_START_PORT, LONG_END_PORT, LONG_PORTS, LONG_TOP_PORTS])
+                .value_parser(|x: &str| {
+                    // Validate the port
+                    let val: u16 = x.parse().map_err(|_| format!("`{x}` is not a valid port"))?;
+                    if !(MIN_PORT..=MAX_PORT).contains(&val) {
+                        Err(format!("Port must be between {MIN_PORT} and {MAX_PORT}"))
+                    } else {
+                        Ok(val)
+                    }
+                }),
+        )
+        .arg(
+            Arg::new(LONG_PORTS)
+                .short(SHORT_PORTS)
+                .long(LONG_PORTS)
+                .value_name("SPEC")
+                .help(HELP_PORTS)
+                .conflicts_with_all([LONG_START_PORT, LONG_END_PORT])
+                .value_parser(ranges::parse_spec),
+        )
+        .arg(
+            Arg::new(LONG_EXCLUDE)
+                .long(LONG_EXCLUDE)
+                .value_name("SPEC")
+                .help(HELP_EXCLUDE)
+                .value_parser(ranges::parse_spec),
+        )
+        .arg(
+            Arg::new(LONG_TOP_PORTS)
+                .long(LONG_TOP_PORTS)
+                .value_name("N")
+                .help(HELP_TOP_PORTS)
+                .conflicts_with_all([LONG_PORTS, LONG_START_PORT, LONG_END_PORT])
+                .value_parser(|x: &str| {
+                    // Validate count against the embedded list
+                    let val: usize = x.parse().map_err(|_| format!("`{x}` is not a number"))?;
+                    if (1..=top_ports::TOP_PORTS.len()).contains(&val) {
+                        Ok(val)
+                    } else {
+                        Err(format!("Top ports must be between 1 and {}", top_ports::TOP_PORTS.len()))
+                    }
+                }),
+        )
+        .arg(
+            Arg::new(LONG_RANDOMIZE)
+                .long(LONG_RANDOMIZE)
+                .help(HELP_RANDOMIZE)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_DESCENDING)
+                .long(LONG_DESCENDING)
+                .help(HELP_DESCENDING)
+                .conflicts_with(LONG_RANDOMIZE)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_INTERLEAVE)
+                .long(LONG_INTERLEAVE)
+                .help(HELP_INTERLEAVE)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_SEED)
+                .long(LONG_SEED)
+                .value_name("N")
+                .help(HELP_SEED)
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            Arg::new(LONG_CHUNK_SIZE)
+                .long(LONG_CHUNK_SIZE)
+                .help(HELP_CHUNK_SIZE)
+                .value_parser(|x: &str| {
+                    // Validate chunk size
+                    let val: usize = x.parse().map_err(|_| format!("`{x}` is not a number"))?;
+                    if val == 0 {
+                        Err(String::from("Chunk size must be at least 1"))
+                    } else {
+                        Ok(val)
+                    }
+                }),
+        )
+        .arg(
+            Arg::new(LONG_CHUNK_PAUSE)
+                .long(LONG_CHUNK_PAUSE)
+                .help(HELP_CHUNK_PAUSE)
+                .requires(LONG_CHUNK_SIZE)
+                .default_value(DEFAULT_CHUNK_PAUSE)
+                .value_parser(duration::parse),
+        )
+        .arg(
+            Arg::new(LONG_RESUME)
+                .long(LONG_RESUME)
+                .value_name("FILE")
+                .help(HELP_RESUME)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new(LONG_SQLITE)
+                .long(LONG_SQLITE)
+                .visible_alias(ALIAS_SQLITE)
+                .value_name("PATH")
+                .help(HELP_SQLITE)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new(LONG_DIFF)
+                .long(LONG_DIFF)
+                .help(HELP_DIFF)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_IDENTIFY)
+                .long(LONG_IDENTIFY)
+                .help(HELP_IDENTIFY)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_PROBE_HTTP)
+                .long(LONG_PROBE_HTTP)
+                .help(HELP_PROBE_HTTP)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_TLS_PROBE)
+                .long(LONG_TLS_PROBE)
+                .help(HELP_TLS_PROBE)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_BANNER)
+                .long(LONG_BANNER)
+                .help(HELP_BANNER)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_PROBE_ORDER)
+                .long(LONG_PROBE_ORDER)
+                .value_name("LIST")
+                .help(HELP_PROBE_ORDER)
+                .default_value(DEFAULT_PROBE_ORDER)
+                // Validate the probe names
+                .value_parser(probe_order::parse),
+        )
+        .arg(
+            Arg::new(LONG_BANNER_BYTES)
+                .long(LONG_BANNER_BYTES)
+                .value_name("N")
+                .help(HELP_BANNER_BYTES)
+                .default_value(DEFAULT_BANNER_BYTES)
+                .value_parser(|x: &str| {
+                    // Validate the size limit
+                    let val: usize = x.parse().map_err(|_| format!("`{x}` is not a number"))?;
+                    if (1..=MAX_BANNER_BYTES).contains(&val) {
+                        Ok(val)
+                    } else {
+                        Err(format!("Banner bytes must be between 1 and {MAX_BANNER_BYTES}"))
+                    }
+                }),
+        )
+        .arg(
+            Arg::new(LONG_MIN_RESPONSE_BYTES)
+                .long(LONG_MIN_RESPONSE_BYTES)
+                .value_name("N")
+                .help(HELP_MIN_RESPONSE_BYTES)
+                .value_parser(|x: &str| {
+                    // Validate the shortest banner kept
+                    let val: usize = x.parse().map_err(|_| format!("`{x}` is not a number"))?;
+                    if (1..=MAX_BANNER_BYTES).contains(&val) {
+                        Ok(val)
+                    } else {
+                        Err(format!("Minimum response bytes must be between 1 and {MAX_BANNER_BYTES}"))
+                    }
+                }),
+        )
+        .arg(
+            Arg::new(LONG_READ_TIMEOUT)
+                .long(LONG_READ_TIMEOUT)
+                .visible_alias(ALIAS_READ_TIMEOUT)
+                .value_name("DURATION")
+                .help(HELP_READ_TIMEOUT)
+                .default_value(DEFAULT_READ_TIMEOUT)
+                .value_parser(|x: &str| {
+                    // Validate the wait is non-zero and bounded
+                    let val = duration::parse_non_zero(x)?;
+                    if val > MAX_READ_TIMEOUT {
+                        Err(format!("Read timeout must be at most {}s", MAX_READ_TIMEOUT.as_secs()))
+                    } else {
+                        Ok(val)
+                    }
+                }),
+        )
+        .arg(
+            Arg::new(LONG_WAIT_BEFORE_CLOSE)
+                .long(LONG_WAIT_BEFORE_CLOSE)
+                .value_name("DURATION")
+                .help(HELP_WAIT_BEFORE_CLOSE)
+                .value_parser(|x: &str| {
+                    // Validate the hold is non-zero and bounded
+                    let val = duration::parse_non_zero(x)?;
+                    if val > MAX_WAIT_BEFORE_CLOSE {
+                        Err(format!("Wait must be at most {}s", MAX_WAIT_BEFORE_CLOSE.as_secs()))
+                    } else {
+                        Ok(val)
+                    }
+                }),
+        )
+        .arg(
+            Arg::new(LONG_NO_SERVICE_NAMES)
+                .long(LONG_NO_SERVICE_NAMES)
+                .help(HELP_NO_SERVICE_NAMES)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_EXIT_ZERO)
+                .long(LONG_EXIT_ZERO)
+                .help(HELP_EXIT_ZERO)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_OPEN_ONLY)
+                .long(LONG_OPEN_ONLY)
+                .help(HELP_OPEN_ONLY)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_QUIET)
+                .short(SHORT_QUIET)
+                .long(LONG_QUIET)
+                .help(HELP_QUIET)
+                .conflicts_with(LONG_PROGRESS)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_PROGRESS)
+                .long(LONG_PROGRESS)
+                .visible_alias(ALIAS_PROGRESS)
+                .help(HELP_PROGRESS)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_PROGRESS_STYLE)
+                .long(LONG_PROGRESS_STYLE)
+                .help(HELP_PROGRESS_STYLE)
+                .default_value(DEFAULT_PROGRESS_STYLE)
+                .value_parser(value_parser!(BarStyle)),
+        )
+        .arg(
+            Arg::new(LONG_VERBOSE)
+                .short(SHORT_VERBOSE)
+                .long(LONG_VERBOSE)
+                .help(HELP_VERBOSE)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_STREAM)
+                .long(LONG_STREAM)
+                .help(HELP_STREAM)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_EMIT_SOCKET)
+                .long(LONG_EMIT_SOCKET)
+                .value_name("PATH")
+                .help(HELP_EMIT_SOCKET)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new(LONG_FLUSH_EVERY)
+                .long(LONG_FLUSH_EVERY)
+                .help(HELP_FLUSH_EVERY)
+                .default_value(DEFAULT_FLUSH_EVERY)
+                .value_parser(|x: &str| {
+                    // Validate flush count
+                    let val: usize = x.parse().map_err(|_| format!("`{x}` is not a number"))?;
+                    if val == 0 {
+                        Err(String::from("Flush count must be at least 1"))
+                    } else {
+                        Ok(val)
+                    }
+                }),
+        )
+        .arg(
+            Arg::new(LONG_FLUSH_INTERVAL)
+                .long(LONG_FLUSH_INTERVAL)
+                .help(HELP_FLUSH_INTERVAL)
+                .default_value(DEFAULT_FLUSH_INTERVAL)
+                .value_parser(duration::parse_non_zero),
+        )
+        .arg(
+            Arg::new(LONG_COALESCE_THRESHOLD)
+                .long(LONG_COALESCE_THRESHOLD)
+                .value_name("N")
+                .help(HELP_COALESCE_THRESHOLD)
+                .value_parser(|x: &str| {
+                    // Validate threshold
+                    let val: usize = x.parse().map_err(|_| format!("`{x}` is not a number"))?;
+                    if val < 2 {
+                        Err(String::from("Coalesce threshold must be at least 2"))
+                    } else {
+                        Ok(val)
+                    }
+                }),
+        )
+        .arg(
+            Arg::new(LONG_REPORT_CLOSED_RANGES)
+                .long(LONG_REPORT_CLOSED_RANGES)
+                .help(HELP_REPORT_CLOSED_RANGES)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_SHOW_ALL)
+                .long(LONG_SHOW_ALL)
+                .help(HELP_SHOW_ALL)
+                .conflicts_with_all([LONG_OPEN_ONLY, LONG_REPORT_CLOSED_RANGES])
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_COUNT_ONLY)
+                .long(LONG_COUNT_ONLY)
+                .help(HELP_COUNT_ONLY)
+                // The count replaces the report and anything else on stdout
+                .conflicts_with_all([LONG_FORMAT, LONG_OUTPUT, LONG_SHOW_ALL, LONG_STREAM, LONG_DRY_RUN])
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_SUMMARY_LINE)
+                .long(LONG_SUMMARY_LINE)
+                .help(HELP_SUMMARY_LINE)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_TIMEOUT_BY_RTT)
+                .long(LONG_TIMEOUT_BY_RTT)
+                .help(HELP_TIMEOUT_BY_RTT)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_FORMAT)
+                .short(SHORT_FORMAT)
+                .long(LONG_FORMAT)
+                .help(HELP_FORMAT)
+                .default_value(DEFAULT_FORMAT)
+                .value_parser(value_parser!(Format)),
+        )
+        .arg(
+            Arg::new(LONG_COLOR)
+                .long(LONG_COLOR)
+                .help(HELP_COLOR)
+                .default_value(DEFAULT_COLOR)
+                .value_parser(value_parser!(ColorChoice)),
+        )
+        .arg(
+            Arg::new(LONG_OUTPUT)
+                .short(SHORT_OUTPUT)
+                .long(LONG_OUTPUT)
+                .value_name("PATH")
+                .help(HELP_OUTPUT)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new(LONG_PROTOCOL)
+                .long(LONG_PROTOCOL)
+                .help(HELP_PROTOCOL)
+                .default_value(DEFAULT_PROTOCOL)
+                .value_parser(value_parser!(Protocol)),
+        )
+        .arg(
+            Arg::new(LONG_CONFIRM_THRESHOLD)
+                .long(LONG_CONFIRM_THRESHOLD)
+                .help(HELP_CONFIRM_THRESHOLD)
+                .default_value(DEFAULT_CONFIRM_THRESHOLD)
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            Arg::new(LONG_YES)
+                .short(SHORT_YES)
+                .long(LONG_YES)
+                .help(HELP_YES)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_ALLOW_PUBLIC)
+                .long(LONG_ALLOW_PUBLIC)
+                .help(HELP_ALLOW_PUBLIC)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_TIMEOUT_RAMP)
+                .long(LONG_TIMEOUT_RAMP)
+                .help(HELP_TIMEOUT_RAMP)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_PROGRESS_FD)
+                .long(LONG_PROGRESS_FD)
+                .value_name("FD")
+                .help(HELP_PROGRESS_FD)
+                .value_parser(value_parser!(i32).range(0..)),
+        )
+        .arg(
+            Arg::new(LONG_VIA)
+                .long(LONG_VIA)
+                .value_name("GATEWAY_IP")
+                .help(HELP_VIA)
+                .value_parser(value_parser!(IpAddr)),
+        )
+        .arg(
+            Arg::new(LONG_SOURCE_IP)
+                .long(LONG_SOURCE_IP)
+                .value_name("IP")
+                .help(HELP_SOURCE_IP)
+                .conflicts_with_all([LONG_VIA, LONG_PROXY])
+                .value_parser(value_parser!(IpAddr)),
+        )
+        .arg(
+            Arg::new(LONG_PROXY)
+                .long(LONG_PROXY)
+                .value_name("URL")
+                .help(HELP_PROXY)
+                // Connections made outside the proxy would bypass it
+                .conflicts_with_all([LONG_VIA, LONG_IDENTIFY, LONG_PROBE_HTTP, LONG_TLS_PROBE, LONG_TIMEOUT_BY_RTT])
+                // Validate the URL shape; the host is resolved later
+                .value_parser(|s: &str| s.parse::<ProxyUrl>()),
+        )
+        .arg(
+            Arg::new(LONG_PRINT_REPRODUCE)
+                .long(LONG_PRINT_REPRODUCE)
+                .help(HELP_PRINT_REPRODUCE)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_DRY_RUN)
+                .long(LONG_DRY_RUN)
+                .help(HELP_DRY_RUN)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_TIMEOUT_AS_OPEN)
+                .long(LONG_TIMEOUT_AS_OPEN)
+                .help(HELP_TIMEOUT_AS_OPEN)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_VERIFY)
+                .long(LONG_VERIFY)
+                .help(HELP_VERIFY)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(LONG_VERIFY_TIMEOUT)
+                .long(LONG_VERIFY_TIMEOUT)
+                .value_name("DURATION")
+                .help(HELP_VERIFY_TIMEOUT)
+                .requires(LONG_VERIFY)
+                .value_parser(|x: &str| {
+                    // Validate timeout range
+                    let val = duration::parse_non_zero(x)?;
+                    if val > MAX_TIMEOUT {
+                        Err(String::from("Timeout must be at most 60000 milliseconds"))
+                    } else {
+                        Ok(val)
+                    }
+                }),
+        )
+        .arg(
+            Arg::new(LONG_SIMULATE_LATENCY)
+                .long(LONG_SIMULATE_LATENCY)
+                .hide(true) // Testing aid, not for normal use
+                .value_parser(duration::parse),
+        )
+        // Every visible flag can also come from the environment, e.g. in containers
+        .mut_args(|arg| {
+            if arg.is_hide_set() {
+                return arg;
+            }
+            let name = env_name(arg.get_id().as_str());
+            // Values stay out of --help, as they may hold a proxy password
+            let arg = arg.env(name).hide_env_values(true);
+            // `1`, `yes` and `on` switch a flag on as well as `true`
+            if matches!(arg.get_action(), ArgAction::SetTrue) { arg.value_parser(BoolishValueParser::new()) } else { arg }
+        })
+}
+
+/* -------------------------
+   Main function
+   ------------------------- */
+
+/// Main asynchronous entry point using Tokio runtime
+#[cfg_attr(not(feature = "deterministic"), tokio::main)]
+#[cfg_attr(feature = "deterministic", tokio::main(flavor = "current_thread"))]
+async fn main() {
+    // Diagnostics for debugging the scanner itself, to stderr; RUST_LOG picks
+    // the level and only errors are shown without it
+    env_logger::init();
+
+    // Parse command-line arguments with clap
+    let matches = command().get_matches();
+
+    // Subcommands bypass the regular scan
+    if let Some((selftest::NAME, _)) = matches.subcommand() {
+        std::process::exit(selftest::run().await);
+    }
+
+    // Load saved defaults: an explicit --config must exist, the default
+    // file is optional. Plans and flags override them
+    let config_path = match matches.get_one::<PathBuf>(LONG_CONFIG) {
+        Some(path) => Some(path.clone()),
+        None => config::default_path().filter(|path| path.is_file()),
+    };
+    let saved = match &config_path {
+        Some(path) => {
+            debug!("reading defaults from {}", path.display());
+            config::load(path, MAX_CONCURRENCY, MAX_TIMEOUT).unwrap_or_else(|e| {
+                eprintln!("Error: invalid config file {e}");
+                std::process::exit(EXIT_ERROR);
+            })
+        }
+        None => config::Config::default(),
+    };
+
+    // Load the scan plan, if any; its values sit between defaults and flags
+    let plan = matches.get_one::<PathBuf>(LONG_PLAN).map(|path| {
+        plan::load(path, MAX_CONCURRENCY, MAX_TIMEOUT).unwrap_or_else(|e| {
+            eprintln!("Error: invalid scan plan {e}");
+            std::process::exit(EXIT_ERROR);
+        })
+    });
+    let plan_ports = plan.as_ref().and_then(|p| p.ports.as_ref());
+    let plan_output = plan.as_ref().and_then(|p| p.output.as_ref());
+
+    // Extract values from CLI arguments
+    let endpoint = matches.get_one::<Endpoint>(LONG_TARGET);
+    let mut targets: Vec<Target> = match (endpoint, matches.get_many::<Target>(LONG_IP)) {
+        (Some(endpoint), _) => vec![endpoint.target.clone()],
+        (None, Some(targets)) => targets.cloned().collect(),
+        (None, None) => plan.as_ref().map(|p| p.targets.clone()).unwrap_or_default(),
+    };
+    if let Some(path) = matches.get_one::<PathBuf>(LONG_TARGET_FILE) {
+        let listed = cidr::read_list(path).unwrap_or_else(|e| {
+            eprintln!("Error: invalid target list {e}");
+            std::process::exit(EXIT_ERROR);
+        });
+        debug!("read {} from {}", count(listed.len(), "target"), path.display());
+        targets.extend(listed);
+    }
+    let include_network = matches.get_flag(LONG_INCLUDE_NETWORK);
+    let all_addresses = matches.get_flag(LONG_ALL_ADDRESSES);
+    let family = matches.get_one::<Family>(LONG_FAMILY).copied().expect("Default ensured by clap");
+    let allow_duplicates = matches.get_flag(LONG_ALLOW_DUPLICATES);
+    let max_hosts = matches.get_one::<usize>(LONG_MAX_HOSTS).copied().expect("Default ensured by clap");
+    let truncate_hosts = matches.get_flag(LONG_TRUNCATE_HOSTS);
+    // Interfaces for zoned IPv6 targets such as `fe80::1%eth0`
+    let scopes: HashMap<IpAddr, u32> = targets
+        .iter()
+        .filter(|target| target.scope_id() != 0)
+        .flat_map(|target| target.hosts(include_network).into_iter().map(|ip| (ip, target.scope_id())))
+        .collect();
+    let mut ips: Vec<IpAddr> = Vec::new();
+    // With --all-addresses, the host name each address came from, so the
+    // report can show which of its addresses answered
+    let mut resolved_from: HashMap<IpAddr, String> = HashMap::new();
+    for target in &targets {
+        let Target::Host(name) = target else {
+            let hosts = target.hosts(include_network);
+            if let Some(ip) = hosts.iter().find(|ip| !family.includes(**ip)) {
+                eprintln!("Error: {target} is {} but --family is {}", cidr::family_label(*ip), family.name());
+                std::process::exit(EXIT_ERROR);
+            }
+            ips.extend(hosts);
+            continue;
+        };
+        // Resolve host names up front and say what will actually be scanned
+        debug!("resolving {name}");
+        let mut resolved = cidr::resolve(name).await.unwrap_or_else(|e| {
+            eprintln!("Error: {e}");
+            std::process::exit(EXIT_ERROR);
+        });
+        resolved.retain(|ip| family.includes(*ip));
+        if resolved.is_empty() {
+            eprintln!("Error: `{name}` has no {} address", if family == Family::Ipv4 { "IPv4" } else { "IPv6" });
+            std::process::exit(EXIT_ERROR);
+        }
+        if !all_addresses {
+            resolved.truncate(1);
+        }
+        let list: Vec<String> = resolved.iter().map(|ip| format!("{ip} ({})", cidr::family_label(*ip))).collect();
+        eprintln!("Resolved {name} to {}", list.join(", "));
+        if all_addresses {
+            resolved_from.extend(resolved.iter().map(|ip| (*ip, name.clone())));
+        }
+        ips.extend(resolved);
+    }
+    if ips.is_empty() {
+        eprintln!("Error: no target given; pass --ip or --target-file, or set `targets` in the scan plan");
+        std::process::exit(EXIT_ERROR);
+    }
+    let concurrency = from_plan(&matches, LONG_CONCURRENCY, plan.as_ref().and_then(|p| p.concurrency).or(saved.concurrency))
+        .unwrap_or_else(|| matches.get_one::<usize>(LONG_CONCURRENCY).copied().unwrap());
+    let start_port = matches.get_one::<u16>(LONG_START_PORT).copied().expect("Default ensured by clap");
+    let end_port = matches.get_one::<u16>(LONG_END_PORT).copied().expect("Default ensured by clap");
+    let top_count = matches.get_one::<usize>(LONG_TOP_PORTS).copied();
+    let single_port = matches.get_one::<u16>(LONG_PORT).copied();
+    let excluded = matches.get_one::<Vec<u16>>(LONG_EXCLUDE);
+    let port_list = matches
+        .get_one::<Vec<u16>>(LONG_PORTS)
+        .cloned()
+        .or(top_count.map(top_ports::top))
+        .or(single_port.map(|port| vec![port]))
+        .or(endpoint.map(|endpoint| vec![endpoint.port]))
+        .or_else(|| {
+            // Planned, then saved ports only fill in when no range was
+            // chosen on the command line
+            let range_given = [LONG_START_PORT, LONG_END_PORT].iter().any(|id| explicit(&matches, id));
+            if range_given { None } else { plan_ports.or(saved.ports.as_ref()).cloned() }
+        });
+    let chunk_size = matches.get_one::<usize>(LONG_CHUNK_SIZE).copied();
+    let chunk_pause = matches.get_one::<Duration>(LONG_CHUNK_PAUSE).copied().expect("Default ensured by clap");
+    let resume_path = matches.get_one::<PathBuf>(LONG_RESUME);
+    let sqlite_path = matches.get_one::<PathBuf>(LONG_SQLITE).or(plan_output.and_then(|o| o.sqlite.as_ref()));
+    let diff = matches.get_flag(LONG_DIFF);
+    let identify = matches.get_flag(LONG_IDENTIFY);
+    let probe_http = matches.get_flag(LONG_PROBE_HTTP);
+    let tls_probe = matches.get_flag(LONG_TLS_PROBE);
+    let grab_banner = matches.get_flag(LONG_BANNER);
+    let probe_order = matches.get_one::<Vec<Probe>>(LONG_PROBE_ORDER).cloned().expect("Default ensured by clap");
+    let banner_bytes = matches.get_one::<usize>(LONG_BANNER_BYTES).copied().expect("Default ensured by clap");
+    let min_response_bytes = matches.get_one::<usize>(LONG_MIN_RESPONSE_BYTES).copied();
+    let read_timeout = matches.get_one::<Duration>(LONG_READ_TIMEOUT).copied().expect("Default ensured by clap");
+    let wait_before_close = matches.get_one::<Duration>(LONG_WAIT_BEFORE_CLOSE).copied();
+    let service_names = !matches.get_flag(LONG_NO_SERVICE_NAMES);
+    let exit_zero = matches.get_flag(LONG_EXIT_ZERO);
+    let open_only = matches.get_flag(LONG_OPEN_ONLY);
+    let quiet = matches.get_flag(LONG_QUIET);
+    let force_progress = matches.get_flag(LONG_PROGRESS);
+    let bar_style = matches.get_one::<BarStyle>(LONG_PROGRESS_STYLE).copied().expect("Default ensured by clap");
+    let verbose = matches.get_flag(LONG_VERBOSE);
+    let stream_results = matches.get_flag(LONG_STREAM);
+    let emit_socket = matches.get_one::<PathBuf>(LONG_EMIT_SOCKET);
+    let flush_every = matches.get_one::<usize>(LONG_FLUSH_EVERY).copied().expect("Default ensured by clap");
+    let flush_interval = matches.get_one::<Duration>(LONG_FLUSH_INTERVAL).copied().expect("Default ensured by clap");
+    let coalesce_threshold = matches.get_one::<usize>(LONG_COALESCE_THRESHOLD).copied();
+    let report_closed_ranges = matches.get_flag(LONG_REPORT_CLOSED_RANGES);
+    let show_all = matches.get_flag(LONG_SHOW_ALL);
+    let count_only = matches.get_flag(LONG_COUNT_ONLY);
+    let summary_line = matches.get_flag(LONG_SUMMARY_LINE);
+    let timeout_by_rtt = matches.get_flag(LONG_TIMEOUT_BY_RTT);
+    let format = from_plan(&matches, LONG_FORMAT, plan_output.and_then(|o| o.format).or(saved.format))
+        .unwrap_or_else(|| matches.get_one::<Format>(LONG_FORMAT).copied().expect("Default ensured by clap"));
+    let output_path = matches.get_one::<PathBuf>(LONG_OUTPUT);
+    let color = matches.get_one::<ColorChoice>(LONG_COLOR).copied().expect("Default ensured by clap");
+    let protocol = from_plan(&matches, LONG_PROTOCOL, plan.as_ref().and_then(|p| p.protocol))
+        .unwrap_or_else(|| matches.get_one::<Protocol>(LONG_PROTOCOL).copied().expect("Default ensured by clap"));
+    let connect_timeout = from_plan(&matches, LONG_TIMEOUT, plan.as_ref().and_then(|p| p.timeout).or(saved.timeout))
+        .unwrap_or_else(|| matches.get_one::<Duration>(LONG_TIMEOUT).copied().expect("Default ensured by clap"));
+    let retries = matches.get_one::<usize>(LONG_RETRIES).copied().expect("Default ensured by clap");
+    let backoff_mode = matches.get_one::<BackoffMode>(LONG_BACKOFF_MODE).copied().expect("Default ensured by clap");
+    let backoff_max = matches.get_one::<Duration>(LONG_BACKOFF_MAX).copied().expect("Default ensured by clap");
+    let rate = matches.get_one::<u32>(LONG_RATE).copied();
+    let adaptive = matches.get_flag(LONG_ADAPTIVE);
+    let max_duration = matches.get_one::<Duration>(LONG_MAX_DURATION).copied();
+    let fail_fast = matches.get_flag(LONG_FAIL_FAST);
+    let max_open = matches.get_one::<usize>(LONG_MAX_OPEN).copied();
+    let confirm_threshold = matches.get_one::<u64>(LONG_CONFIRM_THRESHOLD).copied().expect("Default ensured by clap");
+    let assume_yes = matches.get_flag(LONG_YES);
+    let allow_public = matches.get_flag(LONG_ALLOW_PUBLIC);
+    let timeout_ramp = matches.get_flag(LONG_TIMEOUT_RAMP);
+    let progress_fd = matches.get_one::<i32>(LONG_PROGRESS_FD).copied();
+    let via = matches.get_one::<IpAddr>(LONG_VIA).copied();
+    let source_ip = matches.get_one::<IpAddr>(LONG_SOURCE_IP).copied();
+    let proxy_url = matches.get_one::<ProxyUrl>(LONG_PROXY);
+    let print_reproduce = matches.get_flag(LONG_PRINT_REPRODUCE);
+    let dry_run = matches.get_flag(LONG_DRY_RUN);
+    let timeout_as_open = matches.get_flag(LONG_TIMEOUT_AS_OPEN);
+    let verify = matches.get_flag(LONG_VERIFY);
+    let verify_timeout =
+        matches.get_one::<Duration>(LONG_VERIFY_TIMEOUT).copied().unwrap_or_else(|| (connect_timeout * VERIFY_TIMEOUT_FACTOR).min(MAX_TIMEOUT));
+    let simulate_latency = matches.get_one::<Duration>(LONG_SIMULATE_LATENCY).copied();
+
+    // Silence is the normal UDP answer, so its timeouts say nothing about congestion
+    if adaptive && protocol == Protocol::Udp {
+        eprintln!("Error: --adaptive needs TCP or SCTP; UDP timeouts are not a congestion signal");
+        std::process::exit(EXIT_ERROR);
+    }
+
+    // Streamed lines would corrupt a machine-readable report on stdout
+    if stream_results && format != Format::Text && output_path.is_none() {
+        eprintln!("Error: --stream prints to stdout, which would mix with the {} report; write the report with --output", format.name());
+        std::process::exit(EXIT_ERROR);
+    }
+    // JSON lines on stdout are printed as the ports are found, not at the end
+    let stream_jsonl = format == Format::Jsonl && output_path.is_none() && !count_only;
+
+    // The previous run to compare with lives in the database
+    if diff && sqlite_path.is_none() {
+        eprintln!("Error: --diff compares with earlier runs recorded by --sqlite; pass --sqlite PATH or set output.sqlite in the scan plan");
+        std::process::exit(EXIT_ERROR);
+    }
+
+    // Banners are cut at --banner-bytes, so a longer minimum drops them all
+    if let Some(min) = min_response_bytes {
+        if min > banner_bytes {
+            eprintln!("Error: --{LONG_MIN_RESPONSE_BYTES} {min} is more than --{LONG_BANNER_BYTES} {banner_bytes}, so no banner could qualify");
+            std::process::exit(EXIT_ERROR);
+        }
+        if !grab_banner && wait_before_close.is_none() {
+            eprintln!("Warning: --{LONG_MIN_RESPONSE_BYTES} has no effect without --{LONG_BANNER} or --{LONG_WAIT_BEFORE_CLOSE}");
+        }
+    }
+
+    // An explicit or top-N port list replaces the range
+    let mut ports: Vec<u16> = match &port_list {
+        Some(list) => list.clone(),
+        None => {
+            // Ensure start_port <= end_port
+            if start_port > end_port {
+                eprintln!("Error: start_port ({start_port}) cannot be greater than end_port ({end_port})");
+                std::process::exit(EXIT_ERROR);
+            }
+            (start_port..=end_port).collect()
+        }
+    };
+    if let Some(excluded) = excluded {
+        ports.retain(|port| excluded.binary_search(port).is_err());
+        if ports.is_empty() {
+            eprintln!("Error: --{LONG_EXCLUDE} leaves no ports to scan");
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+
+    // Repeated addresses and overlapping ranges would scan a host twice;
+    // keep the first occurrence of each, in target order
+    if !allow_duplicates {
+        let listed = ips.len();
+        let mut seen = HashSet::new();
+        ips.retain(|ip| seen.insert(*ip));
+        let collapsed = listed - ips.len();
+        if collapsed > 0 {
+            eprintln!(
+                "Warning: {} listed more than once; skipping {} (pass --{LONG_ALLOW_DUPLICATES} to scan them again)",
+                count(collapsed, "host"),
+                count(collapsed * ports.len(), "duplicate host/port pair")
+            );
+        }
+    }
+
+    // Ranges and target files can add up to far more hosts than intended;
+    // say how many there are and stop at --max-hosts
+    if matches.contains_id(LONG_TARGET_FILE) || targets.iter().any(|target| matches!(target, Target::Network(..))) {
+        eprintln!("Targets expand to {}", count(ips.len(), "host"));
+    }
+    if ips.len() > max_hosts {
+        if !truncate_hosts {
+            eprintln!(
+                "Error: the targets expand to {}, more than --{LONG_MAX_HOSTS} {max_hosts}; raise the limit or pass --{LONG_TRUNCATE_HOSTS} \
+to scan the first {max_hosts}",
+                count(ips.len(), "host")
+            );
+            std::process::exit(EXIT_ERROR);
+        }
+        eprintln!(
+            "Warning: the targets expand to {}; scanning only the first {max_hosts} (--{LONG_MAX_HOSTS})",
+            count(ips.len(), "host")
+        );
+        ips.truncate(max_hosts);
+    }
+
+    // Attempt order; `ports` itself stays sorted for the report. A random
+    // seed is still drawn explicitly so --print-reproduce can show it; it
+    // also draws the full-jitter retry pauses
+    let randomize = matches.get_flag(LONG_RANDOMIZE);
+    let seed = matches.get_one::<u64>(LONG_SEED).copied().unwrap_or_else(rand::random);
+    let descending = matches.get_flag(LONG_DESCENDING);
+    let interleave = matches.get_flag(LONG_INTERLEAVE);
+    let mut scan_order = ports.clone();
+    if randomize {
+        scan_order.shuffle(&mut StdRng::seed_from_u64(seed));
+    } else if descending {
+        scan_order.reverse();
+    }
+
+    // Effective settings as an explicit command line, for bug reports and audits
+    if print_reproduce {
+        let mut command = reproduce::Reproduce::new(APP_NAME);
+        match (endpoint, top_count, &port_list) {
+            (Some(endpoint), _, _) => command.opt(LONG_TARGET, endpoint),
+            (None, Some(count), _) => command.opt(LONG_IP, targets.iter().map(Target::to_string).collect::<Vec<_>>().join(",")).opt(LONG_TOP_PORTS, count),
+            (None, None, Some(_)) => command.opt(LONG_IP, targets.iter().map(Target::to_string).collect::<Vec<_>>().join(",")).opt(LONG_PORTS, ranges::format_spec(&ports)),
+            (None, None, None) => command
+                .opt(LONG_IP, targets.iter().map(Target::to_string).collect::<Vec<_>>().join(","))
+                .opt(LONG_START_PORT, start_port)
+                .opt(LONG_END_PORT, end_port),
+        };
+        command.opt_some(LONG_EXCLUDE, excluded.map(|list| ranges::format_spec(list)));
+        let jitter = retries > 0 && backoff_mode == BackoffMode::FullJitter;
+        command.flag(LONG_RANDOMIZE, randomize);
+        if randomize || jitter {
+            command.opt(LONG_SEED, seed);
+        }
+        command.flag(LONG_DESCENDING, descending);
+        if ips.len() > 1 {
+            command.flag(LONG_INTERLEAVE, interleave);
+        }
+        command
+            .opt(LONG_CONCURRENCY, concurrency)
+            .opt_some(LONG_RATE, rate)
+            .duration(LONG_TIMEOUT, connect_timeout)
+            .opt(LONG_RETRIES, retries)
+            .opt(LONG_PROTOCOL, protocol.name())
+            .opt_some(LONG_FORMAT, (!count_only).then(|| format.name()))
+            .opt(LONG_COLOR, color.name())
+            .opt_some(LONG_OUTPUT, output_path.map(|p| p.display()))
+            .opt(LONG_CONFIRM_THRESHOLD, confirm_threshold)
+            .opt_some(LONG_CHUNK_SIZE, chunk_size);
+        if retries > 0 {
+            command.opt(LONG_BACKOFF_MODE, backoff_mode.name()).duration(LONG_BACKOFF_MAX, backoff_max);
+        }
+        if chunk_size.is_some() {
+            command.duration(LONG_CHUNK_PAUSE, chunk_pause);
+        }
+        if let Some(limit) = max_duration {
+            command.duration(LONG_MAX_DURATION, limit);
+        }
+        command.flag(LONG_FAIL_FAST, fail_fast).opt_some(LONG_MAX_OPEN, max_open);
+        command.opt_some(LONG_EMIT_SOCKET, emit_socket.map(|p| p.display()));
+        if emit_socket.is_some() {
+            command.opt(LONG_FLUSH_EVERY, flush_every).duration(LONG_FLUSH_INTERVAL, flush_interval);
+        }
+        command
+            .opt_some(LONG_RESUME, resume_path.map(|p| p.display()))
+            .opt_some(LONG_SQLITE, sqlite_path.map(|p| p.display()))
+            .flag(LONG_DIFF, diff)
+            .opt_some(LONG_COALESCE_THRESHOLD, coalesce_threshold)
+            .opt_some(LONG_VIA, via)
+            .opt_some(LONG_SOURCE_IP, source_ip)
+            .opt_some(LONG_PROXY, proxy_url)
+            .opt_some(LONG_PROGRESS_FD, progress_fd)
+            .flag(LONG_INCLUDE_NETWORK, include_network)
+            .flag(LONG_ALL_ADDRESSES, all_addresses)
+            .opt(LONG_FAMILY, family.name())
+            .flag(LONG_ALLOW_DUPLICATES, allow_duplicates)
+            .opt(LONG_MAX_HOSTS, max_hosts)
+            .flag(LONG_TRUNCATE_HOSTS, truncate_hosts)
+            .flag(LONG_IDENTIFY, identify)
+            .flag(LONG_PROBE_HTTP, probe_http)
+            .flag(LONG_TLS_PROBE, tls_probe)
+            .flag(LONG_BANNER, grab_banner)
+            .flag(LONG_NO_SERVICE_NAMES, !service_names)
+            .flag(LONG_EXIT_ZERO, exit_zero)
+            .flag(LONG_OPEN_ONLY, open_only)
+            .flag(LONG_QUIET, quiet)
+            .flag(LONG_PROGRESS, force_progress)
+            .opt(LONG_PROGRESS_STYLE, bar_style.name())
+            .flag(LONG_ADAPTIVE, adaptive)
+            .flag(LONG_VERBOSE, verbose)
+            .flag(LONG_STREAM, stream_results)
+            .flag(LONG_REPORT_CLOSED_RANGES, report_closed_ranges)
+            .flag(LONG_SHOW_ALL, show_all)
+            .flag(LONG_COUNT_ONLY, count_only)
+            .flag(LONG_SUMMARY_LINE, summary_line)
+            .flag(LONG_TIMEOUT_BY_RTT, timeout_by_rtt)
+            .flag(LONG_TIMEOUT_RAMP, timeout_ramp)
+            .flag(LONG_TIMEOUT_AS_OPEN, timeout_as_open)
+            .flag(LONG_VERIFY, verify)
+            .flag(LONG_YES, assume_yes)
+            .flag(LONG_ALLOW_PUBLIC, allow_public);
+        if grab_banner || identify || probe_http || tls_probe {
+            command.duration(LONG_READ_TIMEOUT, read_timeout);
+        }
+        if [grab_banner, identify, probe_http, tls_probe].iter().filter(|&&on| on).count() > 1 {
+            command.opt(LONG_PROBE_ORDER, probe_order::format(&probe_order));
+        }
+        if verify {
+            command.duration(LONG_VERIFY_TIMEOUT, verify_timeout);
+        }
+        if let Some(wait) = wait_before_close {
+            command.duration(LONG_WAIT_BEFORE_CLOSE, wait);
+        }
+        if grab_banner || wait_before_close.is_some() {
+            command.opt(LONG_BANNER_BYTES, banner_bytes).opt_some(LONG_MIN_RESPONSE_BYTES, min_response_bytes);
+        }
+        if let Some(latency) = simulate_latency {
+            command.duration(LONG_SIMULATE_LATENCY, latency);
+        }
+        eprintln!("Reproduce: {}", command.line());
+    }
+
+    // Show the expanded scan and stop before anything touches the network
+    // or the filesystem
+    if dry_run {
+        println!(
+            "Dry run: {} x {} = {}",
+            count(ips.len(), "host"),
+            count(ports.len(), "port"),
+            count(ips.len() * ports.len(), &format!("{} attempt", protocol.name()))
+        );
+        let hosts: Vec<String> = ips.iter().take(DRY_RUN_SAMPLE).map(IpAddr::to_string).collect();
+        let more = ips.len().saturating_sub(DRY_RUN_SAMPLE);
+        println!("Hosts: {}{}", hosts.join(", "), if more > 0 { format!(", ... ({more} more)") } else { String::new() });
+        println!("Ports: {}", ranges::format_ranges(&ranges::coalesce(&ports)));
+        println!("First attempts:");
+        // Every host has the same ports, so interleaving is port-major
+        let first: Vec<(IpAddr, u16)> = if interleave {
+            scan_order.iter().flat_map(|&port| ips.iter().map(move |&ip| (ip, port))).take(DRY_RUN_SAMPLE).collect()
+        } else {
+            ips.iter().flat_map(|&ip| scan_order.iter().map(move |&port| (ip, port))).take(DRY_RUN_SAMPLE).collect()
+        };
+        for (ip, port) in first {
+            println!("  {}", socket_addr(ip, port, scopes.get(&ip).copied().unwrap_or(0)));
+        }
+        return;
+    }
+
+    // Scanning someone else's network can have legal consequences
+    if !guard::allow_public(&ips, allow_public) {
+        std::process::exit(EXIT_ERROR);
+    }
+
+    // Bind to the requested local address, which must exist on this machine
+    if let Some(source) = source_ip {
+        if let Some(ip) = ips.iter().find(|ip| ip.is_ipv4() != source.is_ipv4()) {
+            eprintln!("Error: source address {source} and target {ip} are different address families");
+            std::process::exit(EXIT_ERROR);
+        }
+        if let Err(e) = std::net::UdpSocket::bind((source, 0)) {
+            eprintln!("Error: cannot use {source} as the source address: {e}");
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+
+    // Pick the source address facing the requested gateway
+    let gateway_source = via.map(|gateway| {
+        if let Some(ip) = ips.iter().find(|ip| ip.is_ipv4() != gateway.is_ipv4()) {
+            eprintln!("Error: gateway {gateway} and target {ip} are different address families");
+            std::process::exit(EXIT_ERROR);
+        }
+        let source = route::source_for_gateway(gateway).unwrap_or_else(|e| {
+            eprintln!("Error: no route to gateway {gateway}: {e}");
+            std::process::exit(EXIT_ERROR);
+        });
+        for ip in &ips {
+            eprintln!("Path: {source} -> {gateway} -> {ip} (connections bound to {source})");
+        }
+        source
+    });
+    let source = source_ip.or(gateway_source);
+
+    // Fail early if the platform cannot speak the requested protocol
+    if protocol == Protocol::Sctp
+        && let Err(e) = sctp::check_support(&SocketAddr::new(ips[0], ports[0]))
+    {
+        eprintln!("Error: SCTP scanning is not available on this system: {e}");
+        eprintln!("(On Linux, load the kernel module with `modprobe sctp`.)");
+        std::process::exit(EXIT_ERROR);
+    }
+
+    // Resolve the proxy and make sure it lets us in before scanning through it
+    let proxy = match proxy_url {
+        Some(url) => {
+            if protocol != Protocol::Tcp {
+                eprintln!("Error: --proxy only supports TCP; UDP and SCTP over SOCKS are not supported");
+                std::process::exit(EXIT_ERROR);
+            }
+            let proxy = Proxy::resolve(url).await.unwrap_or_else(|e| {
+                eprintln!("Error: cannot resolve proxy {url}: {e}");
+                std::process::exit(EXIT_ERROR);
+            });
+            if simulate_latency.is_none()
+                && let Err(e) = proxy.check().await
+            {
+                eprintln!("Error: proxy {url} is not usable: {e}");
+                std::process::exit(EXIT_ERROR);
+            }
+            Some(proxy)
+        }
+        None => None,
+    };
+
+    // Open the database up front so a bad path fails before scanning
+    let mut db = sqlite_path.map(|path| {
+        db::open(path).unwrap_or_else(|e| {
+            eprintln!("Error: cannot open SQLite database {}: {e}", path.display());
+            std::process::exit(EXIT_ERROR);
+        })
+    });
+
+    // Read what an earlier run finished, then keep appending to the same file
+    let completed = resume_path.map_or_else(HashMap::new, |path| {
+        checkpoint::load(path, protocol.name()).unwrap_or_else(|e| {
+            eprintln!("Error: cannot read resume file {}: {e}", path.display());
+            std::process::exit(EXIT_ERROR);
+        })
+    });
+    let resume = resume_path.map(|path| {
+        let checkpoint = Checkpoint::open(path, protocol.name()).unwrap_or_else(|e| {
+            eprintln!("Error: cannot open resume file {}: {e}", path.display());
+            std::process::exit(EXIT_ERROR);
+        });
+        (path, Arc::new(checkpoint))
+    });
+
+    // Create the report file up front so a bad path fails before scanning
+    let output_file = output_path.map(|path| {
+        let file = File::create(path).unwrap_or_else(|e| {
+            eprintln!("Error: cannot create output file {}: {e}", path.display());
+            std::process::exit(EXIT_ERROR);
+        });
+        (path, file)
+    });
+
+    // Connect the result socket before scanning; failure only warns
+    let mut emitter = match emit_socket {
+        Some(path) => Some(SocketEmitter::connect(path, flush_every, flush_interval).await),
+        None => None,
+    };
+
+    // Adopt the progress descriptor before scanning so a bad one fails early
+    let progress_feed = progress_fd.map(|fd| {
+        let feed = ProgressFeed::open(fd).unwrap_or_else(|e| {
+            eprintln!("Error: cannot use file descriptor {fd} for progress: {e}");
+            std::process::exit(EXIT_ERROR);
+        });
+        Arc::new(Mutex::new(feed))
+    });
+
+    // Every attempt in flight holds a socket; make sure they fit
+    let concurrency = fd_limit::fit_concurrency(concurrency);
+
+    // Total number of connection attempts across all hosts
+    let total_ports: u64 = ports.len() as u64 * ips.len() as u64;
+
+    // Create a shared progress bar; when it is hidden it still counts
+    // attempts for the progress feed and the interrupt note. The bar is
+    // drawn on stderr, so a log file there would fill with redraws; it is
+    // only shown when both streams are terminals, or when forced. A single
+    // attempt has no progress worth drawing unless --progress asks for it
+    let interactive = std::io::stdout().is_terminal() && std::io::stderr().is_terminal();
+    let show_progress = force_progress || (total_ports > 1 && !quiet && interactive);
+    let pb = Arc::new({
+        let pb = if show_progress && std::io::stderr().is_terminal() {
+            ProgressBar::new(total_ports)
+        } else if show_progress {
+            // indicatif draws nothing on a non-terminal by itself
+            ProgressBar::with_draw_target(
+                Some(total_ports),
+                ProgressDrawTarget::term_like_with_hz(Box::new(Term::stderr()), FORCED_PROGRESS_HZ),
+            )
+        } else {
+            ProgressBar::with_draw_target(Some(total_ports), ProgressDrawTarget::hidden())
+        };
+        pb.set_style(bar_style.style());
+        if bar_style == BarStyle::Spinner {
+            pb.enable_steady_tick(SPINNER_TICK);
+        }
+        pb
+    });
+
+    // The enabled probes in order; a banner that comes first is read on the
+    // scan's own connection, as is any banner through a proxy
+    let order: Vec<Probe> = probe_order
+        .iter()
+        .copied()
+        .filter(|probe| match probe {
+            Probe::Tls => tls_probe,
+            Probe::Http => probe_http,
+            Probe::Identify => identify,
+            Probe::Banner => grab_banner,
+        })
+        .collect();
+    let banner_in_scan = grab_banner && (proxy.is_some() || order.first() == Some(&Probe::Banner));
+    let probes = Probes {
+        order,
+        banner_in_scan,
+        banner_bytes,
+        min_banner_bytes: min_response_bytes.unwrap_or(0),
+        source,
+        read_timeout,
+        concurrency,
+    };
+
+    // Without --chunk-size all host/port pairs are a single batch; the hosts
+    // are tuned (resume, RTT, ramp) once the scan is confirmed
+    let mut config = ScanConfig {
+        hosts: ips
+            .iter()
+            .map(|&ip| HostTarget { scope_id: scopes.get(&ip).copied().unwrap_or(0), ..HostTarget::new(ip, scan_order.clone(), connect_timeout) })
+            .collect(),
+        protocol,
+        source,
+        proxy,
+        adaptive: adaptive.then(|| AdaptiveConcurrency::new(concurrency)),
+        chunk_size: chunk_size.unwrap_or(total_ports as usize),
+        chunk_pause,
+        interleave,
+        banner: banner_in_scan,
+        banner_bytes,
+        banner_timeout: read_timeout,
+        wait_before_close,
+        retries,
+        backoff: Backoff::new(backoff_mode, RETRY_BACKOFF, backoff_max, seed),
+        rate,
+        verbose,
+        stream: if stream_jsonl {
+            Some(StreamFormat::JsonLines)
+        } else if stream_results {
+            Some(StreamFormat::Text)
+        } else {
+            None
+        },
+        checkpoint: resume.as_ref().map(|(_, checkpoint)| checkpoint.clone()),
+        simulate_latency,
+        // The terminal bar is one consumer of the library's progress events
+        progress: Some({
+            let pb = pb.clone();
+            Arc::new(move |_: ScanEvent| pb.inc(1))
+        }),
+        max_open: if fail_fast { Some(1) } else { max_open },
+        ..ScanConfig::new(ips[0], scan_order.clone(), concurrency, connect_timeout)
+    };
+
+    // Guard against accidentally huge scans
+    if !guard::confirm(total_ports, confirm_threshold, assume_yes, &config) {
+        eprintln!("Aborted.");
+        std::process::exit(EXIT_ERROR);
+    }
+
+    // First Ctrl-C stops starting new attempts and reports what was found;
+    // a second one quits immediately
+    let interrupted = Arc::new(AtomicBool::new(false));
+    {
+        let stop = config.stop.clone();
+        let interrupted = interrupted.clone();
+        let pb = pb.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            interrupted.store(true, Ordering::Relaxed);
+            stop.store(true, Ordering::Relaxed);
+            pb.suspend(|| eprintln!("Interrupted: finishing attempts in flight, press Ctrl-C again to quit now"));
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(EXIT_INTERRUPTED);
+            }
+        });
+    }
+
+    // The time limit stops the scan the same way, without the exit code
+    let truncated = Arc::new(AtomicBool::new(false));
+    if let Some(limit) = max_duration {
+        let stop = config.stop.clone();
+        let truncated = truncated.clone();
+        let pb = pb.clone();
+        tokio::spawn(async move {
+            sleep(limit).await;
+            if !stop.swap(true, Ordering::Relaxed) {
+                truncated.store(true, Ordering::Relaxed);
+                pb.suspend(|| eprintln!("Time limit of {limit:?} reached: finishing attempts in flight"));
+            }
+        });
+    }
+
+    info!("starting {} scan of {} host(s) x {} port(s)", protocol.name(), ips.len(), ports.len());
+    let started = Instant::now();
+    let started_at = SystemTime::now();
+
+    // Periodic progress events for frontends; stops on the first write error
+    let feed_task = progress_feed.clone().map(|feed| {
+        let pb = pb.clone();
+        let open_count = config.open_count.clone();
+        tokio::spawn(async move {
+            let mut ticker = interval(progress_fd::FEED_INTERVAL);
+            loop {
+                ticker.tick().await;
+                let Ok(mut feed) = feed.lock() else { break };
+                let done = pb.position();
+                let total = pb.length().unwrap_or(0);
+                if let Err(e) = feed.emit("progress", done, total, open_count.load(Ordering::Relaxed), started.elapsed()) {
+                    eprintln!("Warning: progress feed write failed: {e}; no further progress events");
+                    break;
+                }
+            }
+        })
+    });
+
+    // Ports finished by an earlier run of this scan are not repeated
+    let empty = checkpoint::Completed::default();
+    let done: Vec<(&checkpoint::Completed, HashSet<u16>)> = ips
+        .iter()
+        .map(|ip| {
+            let done = completed.get(ip).unwrap_or(&empty);
+            (done, done.ports().filter(|p| ports.binary_search(p).is_ok()).collect())
+        })
+        .collect();
+    pb.inc(done.iter().map(|(_, done_ports)| done_ports.len() as u64).sum());
+    let resumed_open = done.iter().map(|(done, done_ports)| done.open.iter().filter(|p| done_ports.contains(p)).count()).sum();
+    config.open_count.fetch_add(resumed_open, Ordering::Relaxed);
+    if stream_jsonl {
+        for (ip, (done, done_ports)) in ips.iter().zip(&done) {
+            for &port in done.open.iter().filter(|p| done_ports.contains(p)) {
+                pb.suspend(|| println!("{}", json_line(*ip, port)));
+            }
+        }
+    }
+
+    // Short timeouts for nearby hosts, the full budget for distant ones;
+    // the hosts are measured concurrently before the scan starts
+    let calibrate_rtt = timeout_by_rtt && protocol == Protocol::Tcp && simulate_latency.is_none();
+    let (pb_ref, ports_ref) = (&pb, &ports);
+    stream::iter(config.hosts.iter_mut().zip(&done))
+        .for_each_concurrent(concurrency, |(host, (_, done_ports))| async move {
+            host.ports.retain(|p| !done_ports.contains(p));
+            if calibrate_rtt {
+                let ip = host.ip;
+                let probes: Vec<SocketAddr> = ports_ref.iter().take(calibrate::RTT_PROBES).map(|&port| socket_addr(ip, port, host.scope_id)).collect();
+                // One line per host would flood large sweeps, so only with --verbose
+                match calibrate::measure_rtt(&probes, source, connect_timeout).await {
+                    Some(rtt) => {
+                        host.connect_timeout = calibrate::timeout_for_rtt(rtt, connect_timeout);
+                        if verbose {
+                            pb_ref.suspend(|| eprintln!("{ip}: RTT {rtt:.1?}, calibrated timeout {:.1?}", host.connect_timeout));
+                        }
+                    }
+                    None if verbose => pb_ref.suspend(|| eprintln!("{ip}: no reply to RTT probes, keeping timeout {connect_timeout:.1?}")),
+                    None => {}
+                }
+            }
+            host.ramp = timeout_ramp.then(|| TimeoutRamp::new(host.connect_timeout, host.ports.len()));
+        })
+        .await;
+
+    // Scan all hosts at once, sharing the concurrency budget and progress bar
+    let results = if config.stop.load(Ordering::Relaxed) { vec![] } else { scan_ports(&config, pb.clone(), emitter.as_mut()).await };
+    let scan_elapsed = started.elapsed();
+    let attempts_made: usize = results.iter().map(|result| result.scanned).sum();
+    if let Some(adaptive) = &config.adaptive {
+        pb.suspend(|| eprintln!("Adaptive concurrency settled at {} (peak {}, maximum {concurrency})", adaptive.limit(), adaptive.peak()));
+    }
+    let stopped = config.stop.load(Ordering::Relaxed);
+    if let Some((path, checkpoint)) = &resume
+        && let Err(e) = checkpoint.flush()
+    {
+        pb.suspend(|| eprintln!("Warning: cannot write resume file {}: {e}", path.display()));
+    }
+
+    let mut hosts = Vec::with_capacity(ips.len());
+    for ((host, mut scanned), (done, done_ports)) in config.hosts.iter().zip(results).zip(&done) {
+        let ip = host.ip;
+
+        // Merge in the earlier run's results for the ports it finished
+        let mut attempted = std::mem::take(&mut scanned.attempted);
+        attempted.extend(done_ports);
+        if stopped && attempted.is_empty() {
+            continue;
+        }
+        attempted.sort_unstable();
+        scanned.scanned = attempted.len();
+        scanned.open.extend(done.open.iter().filter(|p| done_ports.contains(p)));
+        scanned.open.sort_unstable();
+        scanned.timed_out.extend(done.timed_out.iter().filter(|p| done_ports.contains(p)));
+        scanned.timed_out.sort_unstable();
+
+        // Give the silent ports a second, more patient chance before they
+        // are reported as filtered
+        if verify && !stopped && !scanned.timed_out.is_empty() {
+            verify_pass(&config, host, &mut scanned, verify_timeout, retries.max(1), &pb, stream_jsonl).await;
+        }
+
+        // Re-check hung ports to tell consistent tarpits from one-off timeouts;
+        // silence is normal for UDP, so it has no tarpits
+        let tarpits = if timeout_as_open && !stopped && protocol != Protocol::Udp && !scanned.timed_out.is_empty() {
+            confirm_tarpits(&config, host, &scanned.timed_out).await
+        } else {
+            vec![]
+        };
+
+        // A byte or two is noise, not a greeting
+        if let Some(min) = min_response_bytes {
+            scanned.banners.retain(|_, banner| banner.len() >= min);
+        }
+
+        // Probe open ports for their protocol, web page and TLS, one entry per open port
+        let probed = if probes.any() && !stopped && protocol == Protocol::Tcp && simulate_latency.is_none() {
+            probe_open_ports(host, &scanned, &probes).await
+        } else {
+            vec![]
+        };
+        let mut probed = probed.into_iter();
+
+        if let Some(ramp) = &host.ramp {
+            pb.suspend(|| eprintln!("Timeout ramp ({ip}): {}", ramp.progression()));
+        }
+
+        hosts.push(HostReport {
+            ip,
+            scanned: scanned.scanned,
+            elapsed: scanned.elapsed,
+            open: scanned
+                .open
+                .iter()
+                .map(|&port| {
+                    let probed = probed.next().unwrap_or_default();
+                    OpenPort {
+                        port,
+                        latency: scanned.latencies.get(&port).copied(),
+                        detected: probed.detected,
+                        banner: probed.banner.or_else(|| scanned.banners.get(&port).cloned()),
+                        http: probed.http,
+                        tls: probed.tls,
+                    }
+                })
+                .collect(),
+            timed_out: scanned.timed_out,
+            tarpits,
+            attempted,
+            resolved_from: resolved_from.get(&ip).cloned(),
+        });
+    }
+
+    let elapsed = started.elapsed();
+    info!("scan finished in {elapsed:?}");
+    let open_total: usize = hosts.iter().map(|host| host.open.len()).sum();
+    let scanned_total: usize = hosts.iter().map(|host| host.scanned).sum();
+    let filtered_total: usize = hosts.iter().map(HostReport::filtered).sum();
+
+    if let Some(task) = feed_task {
+        task.abort();
+    }
+    if let Some(feed) = &progress_feed
+        && let Ok(mut feed) = feed.lock()
+    {
+        let _ = feed.emit("done", pb.position(), pb.length().unwrap_or(0), open_total, elapsed);
+    }
+
+    // Finish the progress bar with a message
+    let interrupted = interrupted.load(Ordering::Relaxed);
+    if interrupted {
+        pb.abandon_with_message("Scan interrupted");
+        eprintln!("Scan interrupted: results are partial ({} of {} attempts made)", pb.position(), total_ports);
+    } else if truncated.load(Ordering::Relaxed) {
+        pb.abandon_with_message("Scan truncated");
+        eprintln!(
+            "Scan truncated by --{LONG_MAX_DURATION}: results are partial ({} of {} attempts made)",
+            pb.position(),
+            total_ports
+        );
+    } else if fail_fast && open_total > 0 {
+        pb.finish_with_message("Stopped at the first open port");
+    } else if let Some(max) = max_open.filter(|&max| config.open_count.load(Ordering::Relaxed) >= max && pb.position() < total_ports) {
+        pb.abandon_with_message(format!("Stopped after {max} open port{}", if max == 1 { "" } else { "s" }));
+        eprintln!(
+            "Scan stopped early by --{LONG_MAX_OPEN}: results are partial ({} of {} attempts made)",
+            pb.position(),
+            total_ports
+        );
+    } else {
+        pb.finish_with_message("Scan Completed Successfully!");
+    }
+
+    if let Some(conn) = db.as_mut() {
+        record_and_diff(conn, &hosts, &ports, started_at, diff);
+    }
+
+    // --open-only leaves out the hosts without open ports, but not the
+    // unreachable addresses of a host name
+    let host_count = hosts.len();
+    let host_reports: Vec<HostReport> =
+        hosts.into_iter().filter(|host| !open_only || !host.open.is_empty() || host.unreachable()).collect();
+    let any_open = host_reports.iter().any(|host| !host.open.is_empty());
+    let report = Report { protocol: protocol.name(), elapsed, hosts: host_reports };
+
+    // Only the text report is ever colored
+    let text_options = TextOptions {
+        protocol,
+        palette: Palette::new(format == Format::Text && color.enabled(output_file.is_none())),
+        service_names,
+        show_all,
+        coalesce_threshold,
+        report_closed_ranges,
+        headings: ips.len() > 1,
+        // Hosts are labeled IPv4 or IPv6 when the scan has both
+        mixed_families: ips.iter().any(IpAddr::is_ipv4) && ips.iter().any(IpAddr::is_ipv6),
+        hidden_hosts: host_count - report.hosts.len(),
+        banner_summary: grab_banner && ips.len() > 1,
+        attempts_made,
+        scan_elapsed,
+        chunked: chunk_size.is_some(),
+    };
+    let rendered = match (format, endpoint) {
+        (Format::Html, _) => report::html(&report),
+        (Format::Prometheus, _) => report::prometheus(&report),
+        (Format::Json, _) => report::json(&report),
+        (Format::Csv, _) => report::csv(&report),
+        (Format::Xml, _) => report::xml(&report),
+        (Format::Jsonl, _) => report::jsonl(&report),
+        (Format::Grep, _) => report::grep(&report),
+        // `--target` answers in one line, like `nc -zv`
+        (Format::Text, Some(endpoint)) => report::text_endpoint(&report, endpoint, &text_options),
+        (Format::Text, None) => report::text(&report, &text_options),
+    };
+
+    match output_file {
+        // Just the number, for `$(...)` in scripts
+        _ if count_only => println!("{open_total}"),
+        Some((path, mut file)) => {
+            if let Err(e) = file.write_all(rendered.as_bytes()) {
+                eprintln!("Error: failed to write the report to {}: {e}", path.display());
+                std::process::exit(EXIT_ERROR);
+            }
+        }
+        None => {
+            // Blank line between the progress bar and a text report
+            if format == Format::Text && endpoint.is_none() {
+                println!();
+            }
+            // Streamed JSON lines already went out during the scan
+            if !stream_jsonl {
+                print!("{rendered}");
+            }
+        }
+    }
+
+    // A finished scan starts from scratch next time
+    if let Some((path, _)) = &resume
+        && !config.stop.load(Ordering::Relaxed)
+        && let Err(e) = std::fs::remove_file(path)
+    {
+        eprintln!("Warning: cannot remove resume file {}: {e}", path.display());
+    }
+
+    // Last on stderr, after any warnings, for wrappers that parse it; the
+    // keys keep this order, new ones are only ever appended
+    if summary_line {
+        eprintln!(
+            "SUMMARY scanned={scanned_total} open={open_total} closed={} filtered={filtered_total} elapsed_ms={}",
+            scanned_total - open_total - filtered_total,
+            elapsed.as_millis()
+        );
+    }
+
+    if interrupted {
+        std::process::exit(EXIT_INTERRUPTED);
+    }
+    if !any_open && !exit_zero {
+        std::process::exit(EXIT_NO_OPEN_PORTS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::ffi::OsString;
+    use std::sync::MutexGuard;
+
+    /// Held while the environment is read or changed, as the tests run in
+    /// parallel threads
+    static ENV: Mutex<()> = Mutex::new(());
+
+    /// Exclusive use of the environment for one test. Starts without any
+    /// `PORT_SNIFFER_*` variable, and puts back every variable it touched
+    /// when dropped.
+    struct Env {
+        /// Variables changed so far, with their values before the test
+        saved: Vec<(OsString, Option<OsString>)>,
+        /// Declared last so it is released after the variables are restored
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    impl Env {
+        /// Waits for the environment and clears the scanner's variables, so
+        /// neither other tests nor the calling shell leak into this one.
+        fn lock() -> Self {
+            let lock = ENV.lock().unwrap_or_else(|e| e.into_inner());
+            let mut env = Env { saved: Vec::new(), _lock: lock };
+            let inherited: Vec<OsString> =
+                std::env::vars_os().map(|(name, _)| name).filter(|name| name.to_string_lossy().starts_with(ENV_PREFIX)).collect();
+            for name in inherited {
+                env.change(name, None);
+            }
+            env
+        }
+
+        /// Sets `name` until the end of the test
+        fn set(&mut self, name: &str, value: &str) {
+            self.change(name.into(), Some(value.into()));
+        }
+
+        /// Parses `args` after the program name against this environment.
+        fn parse(&self, args: &[&str]) -> ArgMatches {
+            command().try_get_matches_from([APP_NAME].iter().chain(args)).expect("valid arguments")
+        }
+
+        /// Sets or removes `name`, remembering its first value
+        fn change(&mut self, name: OsString, value: Option<OsString>) {
+            if !self.saved.iter().any(|(saved, _)| *saved == name) {
+                self.saved.push((name.clone(), std::env::var_os(&name)));
+            }
+            // SAFETY: tests only read or change the environment through an
+            // `Env`, whose lock is held here, and the code under test reads
+            // it only while parsing the command line in `Env::parse`
+            unsafe {
+                match value {
+                    Some(value) => std::env::set_var(&name, value),
+                    None => std::env::remove_var(&name),
+                }
+            }
+        }
+    }
+
+    impl Drop for Env {
+        fn drop(&mut self) {
+            for (name, value) in self.saved.drain(..).rev() {
+                // SAFETY: as in `change`; the lock is still held
+                unsafe {
+                    match value {
+                        Some(value) => std::env::set_var(&name, value),
+                        None => std::env::remove_var(&name),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn env_names_follow_the_long_flag() {
+        assert_eq!(env_name(LONG_CONCURRENCY), "PORT_SNIFFER_CONCURRENCY");
+        assert_eq!(env_name(LONG_BANNER_BYTES), "PORT_SNIFFER_BANNER_BYTES");
+    }
+
+    #[test]
+    fn env_fills_in_a_missing_flag_and_counts_as_explicit() {
+        let mut env = Env::lock();
+        env.set(&env_name(LONG_RETRIES), "3");
+        let matches = env.parse(&["--ip", "127.0.0.1"]);
+        assert_eq!(matches.get_one::<usize>(LONG_RETRIES), Some(&3));
+        assert!(explicit(&matches, LONG_RETRIES));
+    }
+
+    #[test]
+    fn flag_beats_env() {
+        let mut env = Env::lock();
+        env.set(&env_name(LONG_RATE), "100");
+        let matches = env.parse(&["--ip", "127.0.0.1", "--rate", "7"]);
+        assert_eq!(matches.get_one::<u32>(LONG_RATE), Some(&7));
+        assert_eq!(matches.value_source(LONG_RATE), Some(ValueSource::CommandLine));
+    }
+
+    #[test]
+    fn env_values_are_validated_like_flags() {
+        let mut env = Env::lock();
+        env.set(&env_name(LONG_BANNER_BYTES), "not a number");
+        assert!(command().try_get_matches_from([APP_NAME, "--ip", "127.0.0.1"]).is_err());
+    }
+
+    #[test]
+    fn env_switches_accept_boolish_values() {
+        let mut env = Env::lock();
+        env.set(&env_name(LONG_QUIET), "yes");
+        env.set(&env_name(LONG_EXIT_ZERO), "0");
+        let matches = env.parse(&["--ip", "127.0.0.1"]);
+        assert!(matches.get_flag(LONG_QUIET));
+        assert!(!matches.get_flag(LONG_EXIT_ZERO));
+        assert!(explicit(&matches, LONG_QUIET));
+    }
+
+    #[test]
+    fn env_is_restored_after_each_test() {
+        let name = env_name(LONG_RETRIES);
+        {
+            let mut env = Env::lock();
+            env.set(&name, "3");
+            env.set(&name, "4");
+        }
+        let env = Env::lock();
+        assert_eq!(std::env::var_os(&name), None);
+        assert!(!explicit(&env.parse(&["--ip", "127.0.0.1"]), LONG_RETRIES));
+    }
+
+    #[test]
+    fn hidden_flags_have_no_env() {
+        let command = command();
+        let hidden = command.get_arguments().find(|arg| arg.get_id() == LONG_SIMULATE_LATENCY).expect("flag exists");
+        assert!(hidden.get_env().is_none());
+        let visible = command.get_arguments().find(|arg| arg.get_id() == LONG_CONCURRENCY).expect("flag exists");
+        assert_eq!(visible.get_env().and_then(|name| name.to_str()), Some("PORT_SNIFFER_CONCURRENCY"));
+    }
+
+    #[test]
+    fn defaults_lose_to_the_plan_and_the_config_file() {
+        let matches = Env::lock().parse(&["--ip", "127.0.0.1"]);
+        assert!(!explicit(&matches, LONG_CONCURRENCY));
+        let layered = |plan: Option<usize>, saved: Option<usize>| {
+            from_plan(&matches, LONG_CONCURRENCY, plan.or(saved)).unwrap_or_else(|| *matches.get_one::<usize>(LONG_CONCURRENCY).unwrap())
+        };
+        assert_eq!(layered(Some(10), Some(20)), 10);
+        assert_eq!(layered(None, Some(20)), 20);
+        assert_eq!(layered(None, None), 50);
+    }
+
+    #[test]
+    fn env_beats_the_plan_and_the_config_file() {
+        let mut env = Env::lock();
+        env.set(&env_name(LONG_TIMEOUT), "750ms");
+        let matches = env.parse(&["--ip", "127.0.0.1"]);
+        assert_eq!(from_plan(&matches, LONG_TIMEOUT, Some(Duration::from_secs(2))), None);
+        assert_eq!(matches.get_one::<Duration>(LONG_TIMEOUT), Some(&Duration::from_millis(750)));
+
+        let matches = env.parse(&["--ip", "127.0.0.1", "--timeout", "300ms"]);
+        assert_eq!(from_plan(&matches, LONG_TIMEOUT, Some(Duration::from_secs(2))), None);
+        assert_eq!(matches.get_one::<Duration>(LONG_TIMEOUT), Some(&Duration::from_millis(300)));
     }
 }
@@ -0,0 +1,209 @@
+//! Declarative JSON scan plans (`--plan <PLAN.json>`).
+//!
+//! A plan describes a scan in one file so it can be generated by other
+//! programs. Every field is optional; a flag given explicitly on the command
+//! line overrides the plan, and the plan overrides built-in defaults.
+//!
+//! ```json
+//! {
+//!   "targets": ["192.168.0.0/24", "db.example.com"],
+//!   "ports": "22,80,443,8000-8100",
+//!   "protocol": "tcp",
+//!   "concurrency": 50,
+//!   "timeout_ms": 1500,
+//!   "output": { "format": "html", "sqlite": "scans.db" }
+//! }
+//! ```
+//!
+//! Targets take the same values as `--ip` and ports the same spec as
+//! `--ports`; a `{ "start": 1, "end": 1024 }` range and a single `target`
+//! are still accepted. Unknown fields are rejected, and every error names
+//! the JSON path of the offending value (e.g. `targets[1]`).
+
+use crate::cidr::{self, Target}; // `target` and `targets` values
+use crate::ranges; // `ports` specs
+use crate::report::Format; // Output format
+use port_sniffer::Protocol; // Transport protocol
+use serde::Deserialize; // Plan deserialization
+use std::path::{Path, PathBuf}; // Plan and output paths
+use std::time::Duration; // Parsed timeout
+
+/// Plan document as written in the file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPlan {
+    /// One host, network or host name (same as `--ip`)
+    target: Option<String>,
+    /// Hosts, networks and host names (same as `--ip`)
+    targets: Option<Vec<String>>,
+    /// Port spec or inclusive range
+    ports: Option<RawPorts>,
+    /// Transport protocol (`tcp`, `sctp` or `udp`)
+    protocol: Option<Protocol>,
+    /// Concurrent connection attempts (same as `--concurrency`)
+    concurrency: Option<usize>,
+    /// Connect timeout in milliseconds (same as `--timeout`)
+    timeout_ms: Option<u64>,
+    /// Where and how results are written
+    output: Option<PlanOutput>,
+}
+
+/// `ports` as written: a spec string or a range object
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "a port spec such as \"22,80,443\" or a range such as {\"start\": 1, \"end\": 1024}")]
+enum RawPorts {
+    /// Same syntax as `--ports`, e.g. `"22,80,443,8000-8100"`
+    Spec(String),
+    /// Inclusive range
+    Range(PortRange),
+}
+
+/// Inclusive port range in a plan
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PortRange {
+    /// First port
+    start: u16,
+    /// Last port
+    end: u16,
+}
+
+/// Output section of a plan
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlanOutput {
+    /// Report format (same names as `--format`)
+    pub format: Option<Format>,
+    /// SQLite database to append results to
+    pub sqlite: Option<PathBuf>,
+}
+
+/// Validated scan plan
+pub struct Plan {
+    /// Targets in the order given, `target` first; empty when the plan
+    /// names none
+    pub targets: Vec<Target>,
+    /// Sorted, deduplicated ports to scan
+    pub ports: Option<Vec<u16>>,
+    /// Transport protocol
+    pub protocol: Option<Protocol>,
+    /// Concurrent connection attempts
+    pub concurrency: Option<usize>,
+    /// Connect timeout
+    pub timeout: Option<Duration>,
+    /// Where and how results are written
+    pub output: Option<PlanOutput>,
+}
+
+/// Reads, parses and validates the plan at `path`, with the same limits as
+/// the matching flags.
+///
+/// Errors are single-line messages that include the JSON path of the bad
+/// value.
+pub fn load(path: &Path, max_concurrency: usize, max_timeout: Duration) -> Result<Plan, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+
+    let deserializer = &mut serde_json::Deserializer::from_str(&text);
+    let raw: RawPlan = serde_path_to_error::deserialize(deserializer)
+        .map_err(|e| format!("{}: at `{}`: {}", path.display(), e.path(), e.inner()))?;
+
+    raw.validate(max_concurrency, max_timeout).map_err(|(at, msg)| format!("{}: at `{at}`: {msg}", path.display()))
+}
+
+impl RawPlan {
+    /// Parses the targets and ports and checks the ranges that the JSON
+    /// types alone cannot express; the error carries the JSON path of the
+    /// bad value.
+    fn validate(self, max_concurrency: usize, max_timeout: Duration) -> Result<Plan, (String, String)> {
+        let mut targets = Vec::new();
+        if let Some(target) = &self.target {
+            targets.push(cidr::parse(target).map_err(|e| (String::from("target"), e))?);
+        }
+        for (i, target) in self.targets.iter().flatten().enumerate() {
+            targets.push(cidr::parse(target).map_err(|e| (format!("targets[{i}]"), e))?);
+        }
+
+        let ports = match self.ports {
+            Some(RawPorts::Spec(spec)) => Some(ranges::parse_spec(&spec).map_err(|e| (String::from("ports"), e))?),
+            Some(RawPorts::Range(range)) => {
+                if range.start == 0 {
+                    return Err((String::from("ports.start"), String::from("port must be between 1 and 65535")));
+                }
+                if range.end == 0 {
+                    return Err((String::from("ports.end"), String::from("port must be between 1 and 65535")));
+                }
+                if range.start > range.end {
+                    return Err((String::from("ports"), format!("start ({}) cannot be greater than end ({})", range.start, range.end)));
+                }
+                Some((range.start..=range.end).collect())
+            }
+            None => None,
+        };
+
+        if let Some(concurrency) = self.concurrency
+            && !(1..=max_concurrency).contains(&concurrency)
+        {
+            return Err((String::from("concurrency"), format!("must be between 1 and {max_concurrency}")));
+        }
+        let timeout = self.timeout_ms.map(Duration::from_millis);
+        if let Some(timeout) = timeout
+            && (timeout.is_zero() || timeout > max_timeout)
+        {
+            return Err((String::from("timeout_ms"), format!("must be between 1 and {}", max_timeout.as_millis())));
+        }
+
+        Ok(Plan { targets, ports, protocol: self.protocol, concurrency: self.concurrency, timeout, output: self.output })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_CONCURRENCY: usize = 65535;
+    const MAX_TIMEOUT: Duration = Duration::from_secs(60);
+
+    fn validate(json: &str) -> Result<Plan, (String, String)> {
+        let raw: RawPlan = serde_json::from_str(json).expect("plan parses");
+        raw.validate(MAX_CONCURRENCY, MAX_TIMEOUT)
+    }
+
+    /// A plan that must be accepted
+    fn plan(json: &str) -> Plan {
+        validate(json).unwrap_or_else(|(at, msg)| panic!("rejected at `{at}`: {msg}"))
+    }
+
+    /// JSON path named by the error for a rejected plan
+    fn error_at(json: &str) -> String {
+        validate(json).err().expect("plan is rejected").0
+    }
+
+    #[test]
+    fn targets_take_networks_and_host_names() {
+        let targets: Vec<String> = plan(r#"{"target": "10.0.0.1", "targets": ["192.168.0.0/30", "db.example.com"]}"#)
+            .targets.iter().map(Target::to_string).collect();
+        assert_eq!(targets, ["10.0.0.1", "192.168.0.0/30", "db.example.com"]);
+    }
+
+    #[test]
+    fn bad_targets_name_their_index() {
+        assert_eq!(error_at(r#"{"targets": ["10.0.0.1", "10.0.0.300/24"]}"#), "targets[1]");
+    }
+
+    #[test]
+    fn ports_take_a_spec_or_a_range() {
+        assert_eq!(plan(r#"{"ports": "443,22,80-81"}"#).ports, Some(vec![22, 80, 81, 443]));
+        assert_eq!(plan(r#"{"ports": {"start": 20, "end": 22}}"#).ports, Some(vec![20, 21, 22]));
+        assert_eq!(error_at(r#"{"ports": "22,,80"}"#), "ports");
+        assert_eq!(error_at(r#"{"ports": {"start": 0, "end": 22}}"#), "ports.start");
+        assert_eq!(error_at(r#"{"ports": {"start": 80, "end": 22}}"#), "ports");
+    }
+
+    #[test]
+    fn limits_match_the_flags() {
+        assert_eq!(plan(r#"{"concurrency": 65535, "timeout_ms": 60000}"#).timeout, Some(MAX_TIMEOUT));
+        assert_eq!(error_at(r#"{"concurrency": 65536}"#), "concurrency");
+        assert_eq!(error_at(r#"{"timeout_ms": 60001}"#), "timeout_ms");
+        assert_eq!(error_at(r#"{"timeout_ms": 0}"#), "timeout_ms");
+    }
+}
@@ -0,0 +1,164 @@
+//! Order of the follow-up probes on open ports (`--probe-order`).
+//!
+//! The enabled probes run one after another on each open port, in the
+//! order given (default `tls,http,identify,banner`). Probes left out of the
+//! list keep their default order after the listed ones.
+//!
+//! Once a probe has shown what a port speaks, the probes for other
+//! protocols are skipped:
+//!
+//! * a completed TLS handshake skips `http` and `banner`, which would only
+//!   talk plaintext to it, and `identify` reports `tls` without connecting;
+//! * an HTTP answer skips `tls` and `banner` (HTTP servers do not speak
+//!   first, so a banner read would only wait out `--read-timeout`), and
+//!   `identify` reports `http`;
+//! * a banner, or an `identify` result other than `tls` and `http`, skips
+//!   `tls` and `http`; `identify` classifies a banner it finds before
+//!   sending probes of its own.
+//!
+//! A skipped probe leaves no result for the port.
+
+use clap::ValueEnum; // Probe names
+
+/// A follow-up probe of an open TCP port
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum Probe {
+    /// TLS handshake (`--tls-probe`)
+    Tls,
+    /// Request for `/` (`--probe-http`)
+    Http,
+    /// Protocol detection (`--identify`)
+    Identify,
+    /// Greeting read (`--banner`)
+    Banner,
+}
+
+/// Order used for the probes `--probe-order` does not list
+pub const DEFAULT_ORDER: [Probe; 4] = [Probe::Tls, Probe::Http, Probe::Identify, Probe::Banner];
+
+impl Probe {
+    /// Name as accepted by `--probe-order`
+    pub fn name(self) -> &'static str {
+        match self {
+            Probe::Tls => "tls",
+            Probe::Http => "http",
+            Probe::Identify => "identify",
+            Probe::Banner => "banner",
+        }
+    }
+}
+
+/// What a port was shown to speak by an earlier probe
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    /// A TLS handshake completed
+    Tls,
+    /// It answered an HTTP request
+    Http,
+    /// Another protocol, e.g. one that greets first like SSH or SMTP
+    Other,
+}
+
+impl Kind {
+    /// Kind of a protocol reported by `--identify`
+    pub fn of_detected(protocol: &str) -> Self {
+        match protocol {
+            "tls" => Kind::Tls,
+            "http" => Kind::Http,
+            _ => Kind::Other,
+        }
+    }
+
+    /// What `--identify` reports for this kind without probing, if known
+    pub fn detected(self) -> Option<&'static str> {
+        match self {
+            Kind::Tls => Some("tls"),
+            Kind::Http => Some("http"),
+            Kind::Other => None,
+        }
+    }
+
+    /// Whether `probe` is pointless on a port of this kind
+    pub fn skips(self, probe: Probe) -> bool {
+        matches!(
+            (self, probe),
+            (Kind::Tls, Probe::Http | Probe::Banner) | (Kind::Http, Probe::Tls | Probe::Banner) | (Kind::Other, Probe::Tls | Probe::Http)
+        )
+    }
+}
+
+/// Parses a comma-separated probe list such as `tls,http,banner` and
+/// appends the probes it leaves out in their default order.
+pub fn parse(value: &str) -> Result<Vec<Probe>, String> {
+    let mut order: Vec<Probe> = Vec::new();
+    for name in value.split(',').map(str::trim) {
+        let probe = Probe::from_str(name, true).map_err(|_| {
+            let names: Vec<&str> = DEFAULT_ORDER.iter().map(|probe| probe.name()).collect();
+            format!("unknown probe `{name}`; expected {}", names.join(", "))
+        })?;
+        if order.contains(&probe) {
+            return Err(format!("probe `{name}` is listed twice"));
+        }
+        order.push(probe);
+    }
+    order.extend(DEFAULT_ORDER.iter().filter(|probe| !order.contains(probe)).collect::<Vec<_>>());
+    Ok(order)
+}
+
+/// The order as `--probe-order` takes it
+pub fn format(order: &[Probe]) -> String {
+    order.iter().map(|probe| probe.name()).collect::<Vec<_>>().join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listed_probes_come_first() {
+        assert_eq!(parse("banner,http").unwrap(), [Probe::Banner, Probe::Http, Probe::Tls, Probe::Identify]);
+        assert_eq!(parse("tls,http,identify,banner").unwrap(), DEFAULT_ORDER);
+    }
+
+    #[test]
+    fn names_are_trimmed_and_case_insensitive() {
+        assert_eq!(parse(" Identify , TLS").unwrap(), [Probe::Identify, Probe::Tls, Probe::Http, Probe::Banner]);
+    }
+
+    #[test]
+    fn unknown_and_repeated_probes_are_rejected() {
+        assert!(parse("tls,ssh").unwrap_err().contains("unknown probe `ssh`"));
+        assert!(parse("http,http").unwrap_err().contains("listed twice"));
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn formats_back_to_the_same_list() {
+        assert_eq!(format(&parse("banner").unwrap()), "banner,tls,http,identify");
+    }
+
+    #[test]
+    fn tls_skips_the_plaintext_probes() {
+        assert!(Kind::Tls.skips(Probe::Http));
+        assert!(Kind::Tls.skips(Probe::Banner));
+        assert!(!Kind::Tls.skips(Probe::Identify));
+        assert_eq!(Kind::Tls.detected(), Some("tls"));
+    }
+
+    #[test]
+    fn http_skips_tls_and_the_banner() {
+        assert!(Kind::Http.skips(Probe::Tls));
+        assert!(Kind::Http.skips(Probe::Banner));
+        assert!(!Kind::Http.skips(Probe::Identify));
+        assert_eq!(Kind::Http.detected(), Some("http"));
+    }
+
+    #[test]
+    fn other_protocols_skip_tls_and_http() {
+        assert_eq!(Kind::of_detected("ssh"), Kind::Other);
+        assert!(Kind::Other.skips(Probe::Tls));
+        assert!(Kind::Other.skips(Probe::Http));
+        assert!(!Kind::Other.skips(Probe::Banner));
+        assert_eq!(Kind::Other.detected(), None);
+    }
+}
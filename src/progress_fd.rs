@@ -0,0 +1,63 @@
+//! Machine-readable progress feed on an inherited file descriptor.
+//!
+//! Frontends that wrap the binary pass `--progress-fd N` and read one JSON
+//! object per line from that descriptor, while stdout and stderr keep the
+//! results and the human progress bar:
+//!
+//! ```text
+//! {"event":"progress","done":512,"total":1024,"open_count":3,"rate":170.4,"elapsed_ms":3004}
+//! {"event":"done","done":1024,"total":1024,"open_count":4,"rate":171.0,"elapsed_ms":5988}
+//! ```
+//!
+//! `progress` events are written periodically during the scan and a single
+//! `done` event at the end. `rate` is attempts per second so far.
+
+use std::fs::File; // Writer over the descriptor
+use std::io::{self, Write}; // Line writes
+use std::time::Duration; // Elapsed time
+
+/// How often `progress` events are written
+pub const FEED_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Writes progress events to a caller-supplied file descriptor.
+pub struct ProgressFeed {
+    /// The inherited descriptor, owned from here on
+    file: File,
+}
+
+impl ProgressFeed {
+    /// Takes ownership of descriptor `fd`, failing if it is not open.
+    #[cfg(unix)]
+    pub fn open(fd: i32) -> io::Result<Self> {
+        use std::os::fd::FromRawFd; // Adopting the descriptor
+
+        // Refuse descriptors that are not open rather than writing to a
+        // number some other file might be given later
+        // SAFETY: F_GETFD only queries flags and has no side effects
+        if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the descriptor is open and was handed to us exclusively by
+        // the parent process via --progress-fd
+        let file = unsafe { File::from_raw_fd(fd) };
+        Ok(Self { file })
+    }
+
+    /// Descriptor passing is a Unix mechanism.
+    #[cfg(not(unix))]
+    pub fn open(_fd: i32) -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "--progress-fd is only supported on Unix"))
+    }
+
+    /// Writes one event line; `event` is `progress` or `done`.
+    pub fn emit(&mut self, event: &str, done: u64, total: u64, open_count: usize, elapsed: Duration) -> io::Result<()> {
+        let secs = elapsed.as_secs_f64();
+        let rate = if secs > 0.0 { done as f64 / secs } else { 0.0 };
+        writeln!(
+            self.file,
+            "{{\"event\":\"{event}\",\"done\":{done},\"total\":{total},\"open_count\":{open_count},\"rate\":{rate:.1},\"elapsed_ms\":{}}}",
+            elapsed.as_millis()
+        )?;
+        self.file.flush()
+    }
+}
@@ -0,0 +1,187 @@
+//! Experimental connect-timeout ramp.
+//!
+//! The scan starts with a short timeout and only lengthens it when the
+//! target shows it needs more time: every timeout raises it by a quarter,
+//! and an answer that arrived in more than half the current budget raises it
+//! to twice that answer's latency. The timeout never shrinks and never
+//! exceeds the configured maximum.
+
+use std::sync::Mutex; // Milestone log
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering}; // Shared ramp state
+use std::time::Duration; // Timeout values
+
+/// Timeout the ramp starts from (capped by the maximum)
+pub const RAMP_START: Duration = Duration::from_millis(500);
+/// Number of progress milestones recorded for the final report
+const MILESTONES: usize = 4;
+
+/// Shared, monotonically increasing connect timeout
+pub struct TimeoutRamp {
+    /// Current timeout in microseconds
+    current_us: AtomicU64,
+    /// Upper bound in microseconds
+    max_us: u64,
+    /// Ports finished so far
+    done: AtomicUsize,
+    /// Ports expected in total
+    total: usize,
+    /// Timeout at the start and at each quarter of the scan
+    milestones: Mutex<Vec<Duration>>,
+}
+
+impl TimeoutRamp {
+    /// Creates a ramp from `RAMP_START` up to `max` for `total` ports.
+    pub fn new(max: Duration, total: usize) -> Self {
+        let start = RAMP_START.min(max);
+        Self {
+            current_us: AtomicU64::new(start.as_micros() as u64),
+            max_us: max.as_micros() as u64,
+            done: AtomicUsize::new(0),
+            total,
+            milestones: Mutex::new(vec![start]),
+        }
+    }
+
+    /// Timeout to use for the next attempt
+    pub fn current(&self) -> Duration {
+        Duration::from_micros(self.current_us.load(Ordering::Relaxed))
+    }
+
+    /// Feeds back one attempt: `latency` is `None` for a timeout, or how long
+    /// the target took to answer. Retries are attempts too, but progress is
+    /// only counted by `port_done`.
+    pub fn observe(&self, latency: Option<Duration>) {
+        let _ = self.current_us.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+            let next = next_timeout(current, latency.map(|l| l.as_micros() as u64), self.max_us);
+            (next > current).then_some(next)
+        });
+    }
+
+    /// Counts one port as finished, after its last retry; ports beyond
+    /// `total` are ignored.
+    pub fn port_done(&self) {
+        // Record the timeout each time another quarter of the scan completes
+        let Ok(done) = self.done.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |done| (done < self.total).then_some(done + 1)) else {
+            return;
+        };
+        // With fewer ports than milestones, one port can complete several quarters
+        let crossed = (done + 1) * MILESTONES / self.total - done * MILESTONES / self.total;
+        if crossed > 0
+            && let Ok(mut milestones) = self.milestones.lock()
+        {
+            let current = self.current();
+            milestones.extend(std::iter::repeat_n(current, crossed));
+        }
+    }
+
+    /// Human-readable progression, e.g. `500ms (0%) -> 625ms (25%) -> ...`
+    pub fn progression(&self) -> String {
+        let milestones = self.milestones.lock().map(|m| m.clone()).unwrap_or_default();
+        milestones
+            .iter()
+            .enumerate()
+            .map(|(i, timeout)| format!("{timeout:.0?} ({}%)", i * 100 / MILESTONES))
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    }
+}
+
+/// The ramp rule, in microseconds: grow by a quarter on a timeout, or to
+/// twice a slow answer's latency; never shrink, never pass `max`.
+pub fn next_timeout(current: u64, latency: Option<u64>, max: u64) -> u64 {
+    let next = match latency {
+        None => current + current / 4,
+        Some(latency) if latency * 2 > current => latency * 2,
+        Some(_) => current,
+    };
+    next.clamp(current, max.max(current))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One second, in microseconds
+    const SECOND: u64 = 1_000_000;
+
+    #[test]
+    fn timeout_grows_by_a_quarter() {
+        assert_eq!(next_timeout(400_000, None, 10 * SECOND), 500_000);
+    }
+
+    #[test]
+    fn slow_answer_doubles_its_latency() {
+        assert_eq!(next_timeout(500_000, Some(300_000), 10 * SECOND), 600_000);
+    }
+
+    #[test]
+    fn fast_answer_keeps_the_timeout() {
+        assert_eq!(next_timeout(500_000, Some(250_000), 10 * SECOND), 500_000);
+        assert_eq!(next_timeout(500_000, Some(1_000), 10 * SECOND), 500_000);
+    }
+
+    #[test]
+    fn never_passes_the_maximum() {
+        assert_eq!(next_timeout(900_000, None, SECOND), SECOND);
+        assert_eq!(next_timeout(500_000, Some(5 * SECOND), SECOND), SECOND);
+        assert_eq!(next_timeout(SECOND, None, SECOND), SECOND);
+    }
+
+    #[test]
+    fn never_shrinks_below_a_lower_maximum() {
+        assert_eq!(next_timeout(2 * SECOND, None, SECOND), 2 * SECOND);
+    }
+
+    #[test]
+    fn starts_at_ramp_start_capped_by_the_maximum() {
+        assert_eq!(TimeoutRamp::new(Duration::from_secs(5), 10).current(), RAMP_START);
+        assert_eq!(TimeoutRamp::new(Duration::from_millis(200), 10).current(), Duration::from_millis(200));
+    }
+
+    #[test]
+    fn milestones_at_each_quarter() {
+        let ramp = TimeoutRamp::new(Duration::from_secs(5), 8);
+        for _ in 0..8 {
+            ramp.observe(None);
+            ramp.port_done();
+        }
+        let progression = ramp.progression();
+        assert_eq!(progression.matches(" -> ").count(), MILESTONES);
+        assert!(progression.starts_with("500ms (0%)"));
+        assert!(progression.ends_with("(100%)"));
+    }
+
+    #[test]
+    fn retries_do_not_count_as_progress() {
+        let ramp = TimeoutRamp::new(Duration::from_secs(5), 4);
+        for _ in 0..4 {
+            // Two retries per port
+            for _ in 0..3 {
+                ramp.observe(None);
+            }
+            ramp.port_done();
+        }
+        // Extra finished ports, e.g. from a second pass, are not counted
+        ramp.port_done();
+        let progression = ramp.progression();
+        assert_eq!(progression.matches(" -> ").count(), MILESTONES);
+        assert!(progression.ends_with("(100%)"));
+    }
+
+    #[test]
+    fn small_scans_record_every_quarter_once() {
+        let ramp = TimeoutRamp::new(Duration::from_secs(5), 2);
+        ramp.port_done();
+        assert!(ramp.progression().ends_with("(50%)"));
+        ramp.port_done();
+        assert_eq!(ramp.progression().matches(" -> ").count(), MILESTONES);
+        assert!(ramp.progression().ends_with("(100%)"));
+    }
+
+    #[test]
+    fn empty_scans_record_only_the_start() {
+        let ramp = TimeoutRamp::new(Duration::from_secs(5), 0);
+        ramp.port_done();
+        assert_eq!(ramp.progression(), "500ms (0%)");
+    }
+}
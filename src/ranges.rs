@@ -0,0 +1,69 @@
+//! Port ranges: parsing `--ports` specs and coalescing port lists into
+//! contiguous ranges for compact output.
+
+use crate::{MAX_PORT, MIN_PORT}; // Valid port bounds
+
+/// Parses a spec such as `22,80,443,8000-8100` into a sorted, deduplicated
+/// port list. Overlapping ranges are merged; empty, reversed or out-of-range
+/// entries are rejected.
+pub fn parse_spec(spec: &str) -> Result<Vec<u16>, String> {
+    let mut ports = vec![];
+    for token in spec.split(',').map(str::trim) {
+        if token.is_empty() {
+            return Err(format!("empty entry in port list `{spec}`"));
+        }
+        let (first, last) = match token.split_once('-') {
+            Some((first, last)) => (parse_port(first.trim(), token)?, parse_port(last.trim(), token)?),
+            None => {
+                let port = parse_port(token, token)?;
+                (port, port)
+            }
+        };
+        if first > last {
+            return Err(format!("range `{token}` starts after it ends"));
+        }
+        ports.extend(first..=last);
+    }
+    ports.sort_unstable();
+    ports.dedup();
+    Ok(ports)
+}
+
+/// Parses one port number of the spec entry `token`.
+fn parse_port(value: &str, token: &str) -> Result<u16, String> {
+    match value.parse::<u16>() {
+        Ok(port) if (MIN_PORT..=MAX_PORT).contains(&port) => Ok(port),
+        _ => Err(format!("`{token}` is not a port or range (ports are {MIN_PORT}-{MAX_PORT})")),
+    }
+}
+
+/// Collapses ascending, deduplicated `ports` into inclusive `(first, last)`
+/// runs of consecutive ports.
+pub fn coalesce(ports: &[u16]) -> Vec<(u16, u16)> {
+    let mut runs: Vec<(u16, u16)> = vec![];
+    for &port in ports {
+        match runs.last_mut() {
+            Some((_, last)) if last.checked_add(1) == Some(port) => *last = port,
+            _ => runs.push((port, port)),
+        }
+    }
+    runs
+}
+
+/// Formats runs as a comma-separated list such as `1-21, 23, 25-79`.
+pub fn format_ranges(runs: &[(u16, u16)]) -> String {
+    join_runs(runs, ", ")
+}
+
+/// Formats ascending `ports` as a `--ports` spec such as `22,80,8000-8100`.
+pub fn format_spec(ports: &[u16]) -> String {
+    join_runs(&coalesce(ports), ",")
+}
+
+/// Joins runs with `separator`, writing single-port runs as a bare number.
+fn join_runs(runs: &[(u16, u16)], separator: &str) -> String {
+    runs.iter()
+        .map(|&(first, last)| if first == last { first.to_string() } else { format!("{first}-{last}") })
+        .collect::<Vec<_>>()
+        .join(separator)
+}
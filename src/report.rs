@@ -0,0 +1,879 @@
+//! Final scan report and its output formats.
+//!
+//! The scan results are gathered into a `Report` once the scan finishes;
+//! each format, the text one included, is a renderer over that struct.
+
+use crate::cidr; // IPv4/IPv6 labels in text headings
+use crate::color::Palette; // Text report colors
+use crate::http_probe::HttpInfo; // `--probe-http` results
+use crate::cidr::Endpoint; // `--target` in the one-line text answer
+use crate::ranges; // Coalesced port ranges in text output
+use crate::services; // Well-known service names for text, CSV, XML and grep
+use crate::tls_probe::TlsInfo; // `--tls-probe` results
+use port_sniffer::{Protocol, json_line}; // Protocol wording in text output; `--format jsonl` lines
+use clap::ValueEnum; // `--format` values
+use serde::{Deserialize, Serialize}; // Format names in scan plans, JSON output
+use std::collections::{BTreeMap, BTreeSet}; // Banners keyed by port in JSON; hosts sharing a banner
+use std::fmt::Write; // Building output strings
+use std::net::IpAddr; // Represents an IP address
+use std::time::Duration; // Scan duration
+
+/// Output format of the final report
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// Human-readable lines (default)
+    Text,
+    /// Self-contained HTML page
+    Html,
+    /// Prometheus text exposition format
+    Prometheus,
+    /// JSON array of per-host objects
+    Json,
+    /// Comma-separated values with a header row
+    Csv,
+    /// XML document with one element per host and open port
+    Xml,
+    /// One JSON object per open port and line, printed as ports are found
+    Jsonl,
+    /// One line per host with its open ports inline, like nmap's `-oG`
+    Grep,
+}
+
+impl Format {
+    /// Name as accepted by `--format`
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Text => "text",
+            Format::Html => "html",
+            Format::Prometheus => "prometheus",
+            Format::Json => "json",
+            Format::Csv => "csv",
+            Format::Xml => "xml",
+            Format::Jsonl => "jsonl",
+            Format::Grep => "grep",
+        }
+    }
+}
+
+/// One open port in the report
+pub struct OpenPort {
+    /// Port number
+    pub port: u16,
+    /// Time the connect took; unknown for ports carried over by `--resume`
+    pub latency: Option<Duration>,
+    /// Protocol detected by `--identify`, if any
+    pub detected: Option<&'static str>,
+    /// Greeting read by `--banner`, if the service sent one
+    pub banner: Option<String>,
+    /// Server header and page title found by `--probe-http`
+    pub http: Option<HttpInfo>,
+    /// TLS handshake result from `--tls-probe`
+    pub tls: Option<TlsInfo>,
+}
+
+/// Results for one scanned host
+pub struct HostReport {
+    /// Scanned host
+    pub ip: IpAddr,
+    /// Number of ports attempted
+    pub scanned: usize,
+    /// Wall time spent on this host
+    pub elapsed: Duration,
+    /// Open ports in ascending order
+    pub open: Vec<OpenPort>,
+    /// Ports whose attempt timed out (open|filtered for UDP), ascending
+    pub timed_out: Vec<u16>,
+    /// Timed-out ports that hung on every confirmation, ascending
+    pub tarpits: Vec<u16>,
+    /// Every port with a result, including those from a resumed run, ascending
+    pub attempted: Vec<u16>,
+    /// Host name the address was resolved from, with `--all-addresses`
+    pub resolved_from: Option<String>,
+}
+
+impl HostReport {
+    /// Number of ports whose attempt timed out
+    pub fn filtered(&self) -> usize {
+        self.timed_out.len()
+    }
+
+    /// Number of ports that refused the connection
+    pub fn closed(&self) -> usize {
+        self.scanned.saturating_sub(self.open.len() + self.filtered())
+    }
+
+    /// Whether this is one of a host name's addresses (`--all-addresses`)
+    /// on which no connection got through: every attempt was refused or
+    /// failed
+    pub fn unreachable(&self) -> bool {
+        self.resolved_from.is_some() && self.scanned > 0 && self.open.is_empty()
+    }
+}
+
+/// Everything a formatter needs about a finished scan
+pub struct Report {
+    /// Transport protocol name (`tcp`, `sctp`)
+    pub protocol: &'static str,
+    /// Wall time of the whole scan
+    pub elapsed: Duration,
+    /// Per-host results, in the order the hosts were scanned
+    pub hosts: Vec<HostReport>,
+}
+
+impl Report {
+    /// Heading naming the target, e.g. `192.168.0.1` or `3 hosts`
+    fn target_label(&self) -> String {
+        match self.hosts.as_slice() {
+            [host] => host.ip.to_string(),
+            hosts => format!("{} hosts", hosts.len()),
+        }
+    }
+}
+
+/// Average rate of `ports` attempts over `elapsed`, to one decimal place
+/// (0 when no time passed).
+pub fn ports_per_second(ports: usize, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 { (ports as f64 / secs * 10.0).round() / 10.0 } else { 0.0 }
+}
+
+/// `n` followed by `noun`, made plural unless `n` is 1 (`1 port`, `2 ports`).
+pub fn count(n: usize, noun: &str) -> String {
+    if n == 1 { format!("{n} {noun}") } else { format!("{n} {noun}s") }
+}
+
+/// Connect latency for the text report: tenths below 10ms (`0.4ms`), whole
+/// milliseconds above (`12ms`).
+fn format_latency(latency: Duration) -> String {
+    let millis = latency.as_secs_f64() * 1_000.0;
+    if millis < 10.0 { format!("{millis:.1}ms") } else { format!("{millis:.0}ms") }
+}
+
+/// What a port that never answered is called: UDP silence is open|filtered
+/// rather than filtered
+pub fn silent_label(protocol: Protocol) -> &'static str {
+    if protocol == Protocol::Udp { "open|filtered" } else { "filtered" }
+}
+
+/// Why a port is not open: what a refusal looks like depends on the protocol
+fn refused_label(protocol: Protocol) -> &'static str {
+    match protocol {
+        Protocol::Tcp => "connection refused",
+        Protocol::Sctp => "association aborted",
+        Protocol::Udp => "port unreachable",
+    }
+}
+
+/// Settings that only the text report uses
+pub struct TextOptions {
+    /// Protocol of the scan, for its wording and port tags
+    pub protocol: Protocol,
+    /// Colors, or none
+    pub palette: Palette,
+    /// `80/tcp open http` rather than a bare `80`
+    pub service_names: bool,
+    /// List every attempted port with its status (`--show-all`)
+    pub show_all: bool,
+    /// Collapse runs of at least this many open ports into one line
+    pub coalesce_threshold: Option<usize>,
+    /// Also list the closed and filtered ports as ranges
+    pub report_closed_ranges: bool,
+    /// Start each host with a heading; set when more than one host was
+    /// targeted, even if `--open-only` leaves a single one
+    pub headings: bool,
+    /// Label the headings IPv4 or IPv6, when the scan has both
+    pub mixed_families: bool,
+    /// Hosts without open ports that `--open-only` left out
+    pub hidden_hosts: usize,
+    /// Group the hosts by identical banner at the end (`--banner` over
+    /// several hosts)
+    pub banner_summary: bool,
+    /// Attempts made by this run, without those carried over by `--resume`
+    pub attempts_made: usize,
+    /// Wall time of the scan itself, without the follow-up probes
+    pub scan_elapsed: Duration,
+    /// The scan paused between chunks
+    pub chunked: bool,
+}
+
+/// Renders the `--target` answer in one line, like `nc -zv`:
+/// `10.0.0.1:22/tcp open (0.4ms)`.
+pub fn text_endpoint(report: &Report, endpoint: &Endpoint, options: &TextOptions) -> String {
+    let palette = options.palette;
+    let status = match report.hosts.first() {
+        Some(host) => match host.open.first() {
+            Some(entry) => match entry.latency {
+                Some(latency) => format!("{} {}", palette.open("open"), palette.dim(&format!("({})", format_latency(latency)))),
+                None => palette.open("open"),
+            },
+            None if host.attempted.is_empty() => String::from("not checked"),
+            None if !host.timed_out.is_empty() => format!("{} (no response)", silent_label(options.protocol)),
+            None => format!("closed ({})", refused_label(options.protocol)),
+        },
+        None => format!("closed ({})", refused_label(options.protocol)),
+    };
+    format!("{endpoint}/{} {status}\n", options.protocol.name())
+}
+
+/// Renders the report as human-readable lines: a tally and the open ports
+/// of each host, then the overall pace of the scan.
+pub fn text(report: &Report, options: &TextOptions) -> String {
+    let mut out = String::new();
+    for (i, host) in report.hosts.iter().enumerate() {
+        if options.headings {
+            if i > 0 {
+                let _ = writeln!(out);
+            }
+            // The host name and family, when there is something to tell apart
+            let labels: Vec<&str> = host
+                .resolved_from
+                .as_deref()
+                .into_iter()
+                .chain(options.mixed_families.then(|| cidr::family_label(host.ip)))
+                .collect();
+            if labels.is_empty() {
+                let _ = writeln!(out, "=== {} ===", host.ip);
+            } else {
+                let _ = writeln!(out, "=== {} ({}) ===", host.ip, labels.join(", "));
+            }
+        }
+        text_host(&mut out, host, options);
+    }
+    // Every host was left out by --open-only
+    if report.hosts.is_empty() {
+        let _ = writeln!(out, "No open ports found.");
+    }
+    // How much of each host name scanned with --all-addresses answered, to
+    // tell a partly broken name from a healthy one
+    let mut names: Vec<&str> = Vec::new();
+    for name in report.hosts.iter().filter_map(|host| host.resolved_from.as_deref()) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    if !names.is_empty() {
+        let _ = writeln!(out);
+    }
+    for name in names {
+        let addresses: Vec<&HostReport> = report.hosts.iter().filter(|host| host.resolved_from.as_deref() == Some(name)).collect();
+        let unreachable: Vec<String> = addresses.iter().filter(|host| host.unreachable()).map(|host| host.ip.to_string()).collect();
+        let _ = write!(
+            out,
+            "{name}: {} of {} address{} reachable",
+            addresses.len() - unreachable.len(),
+            addresses.len(),
+            if addresses.len() == 1 { "" } else { "es" }
+        );
+        if unreachable.is_empty() {
+            let _ = writeln!(out);
+        } else {
+            let _ = writeln!(out, "; {}", options.palette.unreachable(&format!("unreachable: {}", unreachable.join(", "))));
+        }
+    }
+    if options.banner_summary {
+        banner_summary(&mut out, report);
+    }
+    // How many hosts --open-only left out, so a quiet sweep is not mistaken
+    // for a short target list
+    if options.hidden_hosts > 0 {
+        if !report.hosts.is_empty() {
+            let _ = writeln!(out);
+        }
+        let _ = writeln!(out, "{} without open ports not shown (--open-only)", count(options.hidden_hosts, "host"));
+    }
+
+    // Overall pace of this run, for comparing concurrency settings;
+    // in chunked mode the wall time includes the pauses
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "Scanned {} in {:.1}s ({:.0} ports/s{})",
+        count(options.attempts_made, "port"),
+        options.scan_elapsed.as_secs_f64(),
+        ports_per_second(options.attempts_made, options.scan_elapsed),
+        if options.chunked { ", including pauses" } else { "" }
+    );
+    out
+}
+
+/// Writes the hosts grouped by identical banner or HTTP `Server` header,
+/// most common first, e.g. `15 hosts: server="nginx/1.18" on port 80`, so a
+/// sweep reads as an inventory of the software it found.
+fn banner_summary(out: &mut String, report: &Report) {
+    // Hosts and ports by what they answered, quoted as in the port lines
+    let mut groups: BTreeMap<String, (BTreeSet<IpAddr>, BTreeSet<u16>)> = BTreeMap::new();
+    for host in &report.hosts {
+        for entry in &host.open {
+            let server = entry.http.as_ref().and_then(|http| http.server.as_ref());
+            let keys = entry.banner.iter().map(|banner| format!("{banner:?}")).chain(server.map(|server| format!("server={server:?}")));
+            for key in keys {
+                let (hosts, ports) = groups.entry(key).or_default();
+                hosts.insert(host.ip);
+                ports.insert(entry.port);
+            }
+        }
+    }
+    if groups.is_empty() {
+        return;
+    }
+    let mut groups: Vec<_> = groups.into_iter().collect();
+    groups.sort_by(|(a, (a_hosts, _)), (b, (b_hosts, _))| b_hosts.len().cmp(&a_hosts.len()).then_with(|| a.cmp(b)));
+
+    let _ = writeln!(out);
+    let _ = writeln!(out, "Banners across hosts: ");
+    for (key, (hosts, ports)) in groups {
+        let ports: Vec<String> = ports.iter().map(u16::to_string).collect();
+        let _ = writeln!(
+            out,
+            "{}: {key} on port{} {}",
+            count(hosts.len(), "host"),
+            if ports.len() == 1 { "" } else { "s" },
+            ports.join(", ")
+        );
+    }
+}
+
+/// Port label for text output: `80/tcp` with service names, otherwise the
+/// bare number for TCP and a protocol tag for the others (`53/udp`)
+fn port_label(port: u16, options: &TextOptions) -> String {
+    if options.service_names || options.protocol != Protocol::Tcp {
+        format!("{port}/{}", options.protocol.name())
+    } else {
+        port.to_string()
+    }
+}
+
+/// One line per open port, with whatever was learned about it
+fn open_line(entry: &OpenPort, options: &TextOptions) -> String {
+    let palette = options.palette;
+    let mut line = if options.service_names {
+        // `80/tcp open http`, as in nmap; names are only known for TCP
+        let name = match options.protocol {
+            Protocol::Tcp => services::service_name(entry.port),
+            _ => None,
+        };
+        let port = palette.open(&format!("{} open", port_label(entry.port, options)));
+        match name {
+            Some(name) => format!("{port} {}", palette.service(name)),
+            None => port,
+        }
+    } else {
+        palette.open(&port_label(entry.port, options))
+    };
+    if let Some(latency) = entry.latency {
+        line.push_str(&format!(" {}", palette.dim(&format!("({})", format_latency(latency)))));
+    }
+    if let Some(detected) = entry.detected {
+        line.push_str(&format!(" ({detected})"));
+    }
+    // Quoted and escaped so control bytes cannot garble the terminal
+    if let Some(banner) = &entry.banner {
+        line.push_str(&format!(" {banner:?}"));
+    }
+    if let Some(http) = &entry.http {
+        if let Some(server) = &http.server {
+            line.push_str(&format!(" server={server:?}"));
+        }
+        if let Some(title) = &http.title {
+            line.push_str(&format!(" title={title:?}"));
+        }
+    }
+    if let Some(tls) = &entry.tls {
+        line.push_str(&format!(" tls={}", tls_label(tls)));
+        if let Some(cn) = &tls.cn {
+            line.push_str(&format!(" cn={cn:?}"));
+        }
+    }
+    line
+}
+
+/// Writes one host's part of the text report: its tally, its open ports
+/// (or every port with `--show-all`) and the closed ranges and tarpits.
+fn text_host(out: &mut String, host: &HostReport, options: &TextOptions) {
+    let palette = options.palette;
+    let protocol = options.protocol;
+    let silent = silent_label(protocol);
+    let timed_out = &host.timed_out;
+
+    // Tally of every attempt
+    let tally = format!(
+        "{} scanned, {} open, {} closed, {} {silent}",
+        count(host.scanned, "port"),
+        host.open.len(),
+        host.closed(),
+        host.filtered()
+    );
+    let _ = writeln!(out, "{}", palette.dim(&tally));
+    if host.unreachable() {
+        let _ = writeln!(out, "{}", palette.unreachable("Unreachable: every connection was refused or failed"));
+    }
+
+    if options.show_all {
+        // Every attempted port in order, the open ones as usual
+        let _ = writeln!(out, "Ports: ");
+        let mut open = host.open.iter().peekable();
+        for &port in &host.attempted {
+            if let Some(entry) = open.next_if(|entry| entry.port == port) {
+                let _ = writeln!(out, "{}", open_line(entry, options));
+                continue;
+            }
+            let (status, reason) = if timed_out.binary_search(&port).is_err() {
+                ("closed", refused_label(protocol))
+            } else if host.tarpits.binary_search(&port).is_ok() {
+                (silent, "hung on every attempt")
+            } else {
+                (silent, "no response")
+            };
+            let _ = writeln!(out, "{}", palette.dim(&format!("{} {status} ({reason})", port_label(port, options))));
+        }
+    } else if host.open.is_empty() {
+        if !host.unreachable() {
+            let _ = writeln!(out, "No open ports found.");
+        }
+    } else {
+        let _ = writeln!(out, "Open ports: ");
+
+        // Long runs of consecutive ports collapse into one line; shorter
+        // runs and isolated ports are listed individually
+        let open_numbers: Vec<u16> = host.open.iter().map(|entry| entry.port).collect();
+        let tag = if protocol == Protocol::Tcp { String::new() } else { format!("/{}", protocol.name()) };
+        let mut entries = host.open.iter();
+        for (first, last) in ranges::coalesce(&open_numbers) {
+            let len = usize::from(last - first) + 1;
+            if options.coalesce_threshold.is_some_and(|threshold| len >= threshold) {
+                let _ = writeln!(out, "{} ({len} ports)", palette.open(&format!("{first}-{last}{tag}")));
+                entries.nth(len - 1);
+                continue;
+            }
+            for entry in entries.by_ref().take(len) {
+                let _ = writeln!(out, "{}", open_line(entry, options));
+            }
+        }
+    }
+
+    // UDP cannot tell a silent service from a dropped probe
+    if protocol == Protocol::Udp && !options.show_all && !timed_out.is_empty() {
+        let _ = writeln!(out, "open|filtered: {}", ranges::format_ranges(&ranges::coalesce(timed_out)));
+    }
+
+    // Map the gaps: refused ports are closed, timed-out ports are filtered
+    if options.report_closed_ranges {
+        // Only the attempted ports, which differ from the scan list
+        // when interrupted or resumed
+        let closed: Vec<u16> = host
+            .attempted
+            .iter()
+            .copied()
+            .filter(|p| host.open.binary_search_by_key(p, |entry| entry.port).is_err() && timed_out.binary_search(p).is_err())
+            .collect();
+
+        let _ = writeln!(out);
+        if !closed.is_empty() {
+            let _ = writeln!(out, "closed: {}", ranges::format_ranges(&ranges::coalesce(&closed)));
+        }
+        if !timed_out.is_empty() && protocol != Protocol::Udp {
+            let _ = writeln!(out, "filtered: {}", ranges::format_ranges(&ranges::coalesce(timed_out)));
+        }
+    }
+
+    if !host.tarpits.is_empty() {
+        let _ = writeln!(out, "Possibly tarpitted (connect hung on every attempt): ");
+        for p in &host.tarpits {
+            let _ = writeln!(out, "{p}");
+        }
+    }
+}
+
+const HTML_STYLE: &str = "\
+body{font-family:system-ui,sans-serif;margin:2rem;color:#222}\
+h1{font-size:1.4rem}\
+dl{display:grid;grid-template-columns:max-content auto;gap:.25rem 1rem}\
+dt{font-weight:600}\
+table{border-collapse:collapse;margin-top:1rem}\
+th,td{border:1px solid #ccc;padding:.3rem .8rem;text-align:left}\
+th{background:#f0f0f0}\
+tr:nth-child(even) td{background:#fafafa}";
+
+/// Renders the report as a self-contained HTML page.
+///
+/// A single host gets one summary and table; several hosts each get their
+/// own section after an overall summary.
+pub fn html(report: &Report) -> String {
+    let mut out = String::new();
+    let title = format!("Port scan of {}", report.target_label());
+
+    let _ = writeln!(out, "<!DOCTYPE html>");
+    let _ = writeln!(out, "<html lang=\"en\">");
+    let _ = writeln!(out, "<head>");
+    let _ = writeln!(out, "<meta charset=\"utf-8\">");
+    let _ = writeln!(out, "<title>{}</title>", escape_html(&title));
+    let _ = writeln!(out, "<style>{HTML_STYLE}</style>");
+    let _ = writeln!(out, "</head>");
+    let _ = writeln!(out, "<body>");
+    let _ = writeln!(out, "<h1>{}</h1>", escape_html(&title));
+
+    if let [host] = report.hosts.as_slice() {
+        html_host(&mut out, report, host);
+    } else {
+        // Overall summary, then one section per host
+        let scanned: usize = report.hosts.iter().map(|h| h.scanned).sum();
+        let open: usize = report.hosts.iter().map(|h| h.open.len()).sum();
+        let _ = writeln!(out, "<dl>");
+        let _ = writeln!(out, "<dt>Hosts</dt><dd>{}</dd>", report.hosts.len());
+        let _ = writeln!(out, "<dt>Protocol</dt><dd>{}</dd>", escape_html(report.protocol));
+        let _ = writeln!(out, "<dt>Ports scanned</dt><dd>{scanned}</dd>");
+        let _ = writeln!(out, "<dt>Open ports</dt><dd>{open}</dd>");
+        let _ = writeln!(out, "<dt>Duration</dt><dd>{:.2?}</dd>", report.elapsed);
+        let _ = writeln!(out, "</dl>");
+        for host in &report.hosts {
+            let _ = writeln!(out, "<h2>{}</h2>", escape_html(&host.ip.to_string()));
+            html_host(&mut out, report, host);
+        }
+    }
+
+    let _ = writeln!(out, "</body>");
+    let _ = writeln!(out, "</html>");
+    out
+}
+
+/// Writes the summary and open port table of one host.
+fn html_host(out: &mut String, report: &Report, host: &HostReport) {
+    let _ = writeln!(out, "<dl>");
+    let _ = writeln!(out, "<dt>Target</dt><dd>{}</dd>", escape_html(&host.ip.to_string()));
+    if let Some(name) = &host.resolved_from {
+        let _ = writeln!(out, "<dt>Resolved from</dt><dd>{}</dd>", escape_html(name));
+    }
+    let _ = writeln!(out, "<dt>Protocol</dt><dd>{}</dd>", escape_html(report.protocol));
+    let _ = writeln!(out, "<dt>Ports scanned</dt><dd>{}</dd>", host.scanned);
+    let _ = writeln!(out, "<dt>Open ports</dt><dd>{}</dd>", host.open.len());
+    let _ = writeln!(out, "<dt>Duration</dt><dd>{:.2?}</dd>", host.elapsed);
+    let _ = writeln!(out, "</dl>");
+
+    if host.unreachable() {
+        let _ = writeln!(out, "<p>Unreachable: every connection was refused or failed.</p>");
+    } else if host.open.is_empty() {
+        let _ = writeln!(out, "<p>No open ports found.</p>");
+    } else {
+        let _ = writeln!(out, "<table>");
+        let _ = writeln!(
+            out,
+            "<tr><th>Port</th><th>Protocol</th><th>Detected service</th><th>Banner</th><th>HTTP server</th><th>Page title</th><th>TLS</th><th>Certificate CN</th></tr>"
+        );
+        for entry in &host.open {
+            let http = entry.http.as_ref();
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                entry.port,
+                escape_html(report.protocol),
+                escape_html(entry.detected.unwrap_or("")),
+                escape_html(entry.banner.as_deref().unwrap_or("")),
+                escape_html(http.and_then(|h| h.server.as_deref()).unwrap_or("")),
+                escape_html(http.and_then(|h| h.title.as_deref()).unwrap_or("")),
+                entry.tls.as_ref().map_or("", tls_label),
+                escape_html(entry.tls.as_ref().and_then(|t| t.cn.as_deref()).unwrap_or("")),
+            );
+        }
+        let _ = writeln!(out, "</table>");
+    }
+}
+
+/// Escapes text for use in HTML element content and attribute values.
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Renders the report in the Prometheus text exposition format, suitable
+/// for the node_exporter textfile collector.
+///
+/// Only open ports get a `port_open` series, to keep cardinality bounded on
+/// full-range scans.
+pub fn prometheus(report: &Report) -> String {
+    let mut out = String::new();
+    let protocol = escape_label(report.protocol);
+    let hosts: Vec<(String, &HostReport)> =
+        report.hosts.iter().map(|host| (escape_label(&host.ip.to_string()), host)).collect();
+
+    let _ = writeln!(out, "# HELP port_open Port accepted a connection during the last scan.");
+    let _ = writeln!(out, "# TYPE port_open gauge");
+    for (ip, host) in &hosts {
+        for entry in &host.open {
+            let _ = writeln!(out, "port_open{{ip=\"{ip}\",port=\"{}\",protocol=\"{protocol}\"}} 1", entry.port);
+        }
+    }
+
+    let _ = writeln!(out, "# HELP scan_ports_scanned Number of ports attempted in the last scan.");
+    let _ = writeln!(out, "# TYPE scan_ports_scanned gauge");
+    for (ip, host) in &hosts {
+        let _ = writeln!(out, "scan_ports_scanned{{ip=\"{ip}\",protocol=\"{protocol}\"}} {}", host.scanned);
+    }
+
+    let _ = writeln!(out, "# HELP scan_ports_open Number of open ports found in the last scan.");
+    let _ = writeln!(out, "# TYPE scan_ports_open gauge");
+    for (ip, host) in &hosts {
+        let _ = writeln!(out, "scan_ports_open{{ip=\"{ip}\",protocol=\"{protocol}\"}} {}", host.open.len());
+    }
+
+    let _ = writeln!(out, "# HELP scan_duration_seconds Wall time of the last scan.");
+    let _ = writeln!(out, "# TYPE scan_duration_seconds gauge");
+    for (ip, host) in &hosts {
+        let _ = writeln!(out, "scan_duration_seconds{{ip=\"{ip}\",protocol=\"{protocol}\"}} {:.3}", host.elapsed.as_secs_f64());
+    }
+    out
+}
+
+/// Escapes a Prometheus label value (backslash, double quote, newline).
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Shape of each host object in `--format json`
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    /// Scanned host
+    ip: IpAddr,
+    /// Open ports in ascending order
+    open_ports: Vec<u16>,
+    /// Number of ports attempted
+    scanned: usize,
+    /// Number of ports that refused the connection
+    closed: usize,
+    /// Number of ports whose attempt timed out
+    filtered: usize,
+    /// Wall time of the scan in milliseconds
+    elapsed_ms: u128,
+    /// Average attempts per second over `elapsed_ms`
+    ports_per_second: f64,
+    /// Transport protocol name
+    protocol: &'a str,
+    /// Host name the address was resolved from, with `--all-addresses`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolved_from: Option<&'a str>,
+    /// Present and true when no connection to the address got through
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    unreachable: bool,
+    /// Connect latency in milliseconds by port, present when any port is open
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    latency_ms: BTreeMap<u16, f64>,
+    /// Banners by port, present only when `--banner` captured any
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    banners: BTreeMap<u16, &'a str>,
+    /// HTTP server and title by port, present only when `--probe-http` found any
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    http: BTreeMap<u16, &'a HttpInfo>,
+    /// TLS handshake result by port, present only with `--tls-probe`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    tls: BTreeMap<u16, &'a TlsInfo>,
+}
+
+/// Renders the report as a JSON array with one object per host, e.g.
+/// `[{"ip":"192.168.0.1","open_ports":[22,80],"scanned":1024,"closed":1020,"filtered":2,"elapsed_ms":1234,"ports_per_second":829.8,"protocol":"tcp"}]`,
+/// on a single line. A single-host scan is an array of one.
+pub fn json(report: &Report) -> String {
+    let mut hosts = Vec::new();
+    for host in &report.hosts {
+        hosts.push(JsonReport {
+            ip: host.ip,
+            open_ports: host.open.iter().map(|entry| entry.port).collect(),
+            scanned: host.scanned,
+            closed: host.closed(),
+            filtered: host.filtered(),
+            elapsed_ms: host.elapsed.as_millis(),
+            ports_per_second: ports_per_second(host.scanned, host.elapsed),
+            protocol: report.protocol,
+            resolved_from: host.resolved_from.as_deref(),
+            unreachable: host.unreachable(),
+            latency_ms: host.open.iter().filter_map(|entry| Some((entry.port, latency_ms(entry.latency?)))).collect(),
+            banners: host
+                .open
+                .iter()
+                .filter_map(|entry| Some((entry.port, entry.banner.as_deref()?)))
+                .collect(),
+            http: host.open.iter().filter_map(|entry| Some((entry.port, entry.http.as_ref()?))).collect(),
+            tls: host.open.iter().filter_map(|entry| Some((entry.port, entry.tls.as_ref()?))).collect(),
+        });
+    }
+    let mut out = serde_json::to_string(&hosts).expect("report serializes to JSON");
+    out.push('\n');
+    out
+}
+
+/// Latency in milliseconds to two decimal places, as written to JSON
+pub fn latency_ms(latency: Duration) -> f64 {
+    (latency.as_secs_f64() * 100_000.0).round() / 100.0
+}
+
+/// Renders the report as JSON lines, one `{"ip":"192.168.0.1","port":22,"status":"open"}`
+/// object per open port, sorted by host and port. This is what `--format jsonl`
+/// writes to `--output`; on stdout the same lines are printed during the scan.
+pub fn jsonl(report: &Report) -> String {
+    let mut out = String::new();
+    for host in &report.hosts {
+        for entry in &host.open {
+            let _ = writeln!(out, "{}", json_line(host.ip, entry.port));
+        }
+    }
+    out
+}
+
+/// Column order of `--format csv`; new columns are only ever appended
+pub const CSV_HEADER: &str = "ip,port,status,service,banner";
+
+/// Renders the report as CSV: the `CSV_HEADER` row, then one row per open
+/// port. The service is the `--identify` result or else the well-known TCP
+/// name; service and banner are empty when unknown.
+pub fn csv(report: &Report) -> String {
+    let mut out = format!("{CSV_HEADER}\n");
+    for host in &report.hosts {
+        for entry in &host.open {
+            let service = entry
+                .detected
+                .or_else(|| if report.protocol == "tcp" { services::service_name(entry.port) } else { None })
+                .unwrap_or("");
+            let banner = entry.banner.as_deref().unwrap_or("");
+            let _ = writeln!(out, "{},{},open,{},{}", host.ip, entry.port, csv_field(service), csv_field(banner));
+        }
+    }
+    out
+}
+
+/// Renders the report in nmap's greppable layout, one line per host with
+/// tab-separated fields, e.g.
+/// `Host: 192.168.0.1 ()\tPorts: 22/open/tcp//ssh///, 80/open/tcp//http///`.
+/// Each port is `port/state/protocol/owner/service/rpc/version/`; only the
+/// state, protocol and service are known here, the service coming from
+/// `--identify` or else the well-known TCP name. A host without open ports
+/// still gets its line, with an empty port list.
+pub fn grep(report: &Report) -> String {
+    let mut out = String::new();
+    for host in &report.hosts {
+        let ports: Vec<String> = host
+            .open
+            .iter()
+            .map(|entry| {
+                let service = entry
+                    .detected
+                    .or_else(|| if report.protocol == "tcp" { services::service_name(entry.port) } else { None })
+                    .unwrap_or("");
+                format!("{}/open/{}//{}///", entry.port, report.protocol, service.replace(['/', ','], "|"))
+            })
+            .collect();
+        let _ = writeln!(out, "Host: {} ()\tPorts: {}", host.ip, ports.join(", "));
+    }
+    out
+}
+
+/// Renders the report as an XML document: a `scans` root with a `scan`
+/// element per host, holding a `port` element per open port, e.g.
+/// `<scan ip="192.168.0.1" ...><port number="80" status="open" service="http"/></scan>`.
+/// Unknown services, banners and latencies are left out rather than empty.
+pub fn xml(report: &Report) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(out, "<scans protocol=\"{}\" elapsed_ms=\"{}\">", escape_xml(report.protocol), report.elapsed.as_millis());
+    for host in &report.hosts {
+        let _ = write!(
+            out,
+            "  <scan ip=\"{}\" scanned=\"{}\" closed=\"{}\" filtered=\"{}\" elapsed_ms=\"{}\"",
+            host.ip,
+            host.scanned,
+            host.closed(),
+            host.filtered(),
+            host.elapsed.as_millis()
+        );
+        if let Some(name) = &host.resolved_from {
+            let _ = write!(out, " resolved_from=\"{}\"", escape_xml(name));
+        }
+        if host.unreachable() {
+            let _ = write!(out, " unreachable=\"true\"");
+        }
+        if host.open.is_empty() {
+            let _ = writeln!(out, "/>");
+            continue;
+        }
+        let _ = writeln!(out, ">");
+        for entry in &host.open {
+            let _ = write!(out, "    <port number=\"{}\" status=\"open\"", entry.port);
+            let service = entry.detected.or_else(|| if report.protocol == "tcp" { services::service_name(entry.port) } else { None });
+            if let Some(service) = service {
+                let _ = write!(out, " service=\"{}\"", escape_xml(service));
+            }
+            if let Some(latency) = entry.latency {
+                let _ = write!(out, " latency_ms=\"{}\"", latency_ms(latency));
+            }
+            if let Some(banner) = &entry.banner {
+                let _ = write!(out, " banner=\"{}\"", escape_xml(banner));
+            }
+            if let Some(http) = &entry.http {
+                if let Some(server) = &http.server {
+                    let _ = write!(out, " http_server=\"{}\"", escape_xml(server));
+                }
+                if let Some(title) = &http.title {
+                    let _ = write!(out, " http_title=\"{}\"", escape_xml(title));
+                }
+            }
+            if let Some(tls) = &entry.tls {
+                let _ = write!(out, " tls=\"{}\"", tls_label(tls));
+                if let Some(cn) = &tls.cn {
+                    let _ = write!(out, " tls_cn=\"{}\"", escape_xml(cn));
+                }
+            }
+            let _ = writeln!(out, "/>");
+        }
+        let _ = writeln!(out, "  </scan>");
+    }
+    let _ = writeln!(out, "</scans>");
+    out
+}
+
+/// Short TLS result: the version, or `none` without a handshake.
+pub fn tls_label(tls: &TlsInfo) -> &'static str {
+    match (tls.handshake, tls.version) {
+        (true, Some(version)) => version,
+        (true, None) => "yes",
+        (false, _) => "none",
+    }
+}
+
+/// Escapes text for an XML attribute value. Tabs and line breaks become
+/// character references so parsers do not normalize them to spaces, and
+/// control characters that XML 1.0 cannot represent at all become U+FFFD.
+fn escape_xml(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            '\t' => out.push_str("&#9;"),
+            '\n' => out.push_str("&#10;"),
+            '\r' => out.push_str("&#13;"),
+            '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => out.push(char::REPLACEMENT_CHARACTER),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Quotes a CSV field (RFC 4180) when it contains a comma, quote or line
+/// break, doubling any quotes inside.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
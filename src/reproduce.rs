@@ -0,0 +1,64 @@
+//! Normalized command line that reproduces a scan (`--print-reproduce`).
+//!
+//! Every setting is written out explicitly, including defaulted ones, so the
+//! command keeps meaning the same thing even if defaults change later.
+
+use std::fmt::Display; // Flag values
+use std::time::Duration; // Duration flags
+
+/// Builds a shell-ready command line one flag at a time.
+pub struct Reproduce {
+    /// Words of the command line, already shell-quoted
+    words: Vec<String>,
+}
+
+impl Reproduce {
+    /// Starts a command line for `program`.
+    pub fn new(program: &str) -> Self {
+        Self { words: vec![quote(program)] }
+    }
+
+    /// Appends `--name value`.
+    pub fn opt(&mut self, name: &str, value: impl Display) -> &mut Self {
+        self.words.push(format!("--{name}"));
+        self.words.push(quote(&value.to_string()));
+        self
+    }
+
+    /// Appends `--name value` when a value is present.
+    pub fn opt_some(&mut self, name: &str, value: Option<impl Display>) -> &mut Self {
+        if let Some(value) = value {
+            self.opt(name, value);
+        }
+        self
+    }
+
+    /// Appends a duration flag in milliseconds, e.g. `--name 1000ms`.
+    pub fn duration(&mut self, name: &str, value: Duration) -> &mut Self {
+        self.opt(name, format!("{}ms", value.as_millis()))
+    }
+
+    /// Appends `--name` when `set` is true.
+    pub fn flag(&mut self, name: &str, set: bool) -> &mut Self {
+        if set {
+            self.words.push(format!("--{name}"));
+        }
+        self
+    }
+
+    /// The finished command line
+    pub fn line(&self) -> String {
+        self.words.join(" ")
+    }
+}
+
+/// Single-quotes a word for POSIX shells unless it is plainly safe.
+fn quote(word: &str) -> String {
+    let safe = !word.is_empty()
+        && word.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:%=,@+".contains(c));
+    if safe {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
@@ -0,0 +1,43 @@
+//! Path selection for `--via <GATEWAY_IP>`.
+//!
+//! Changing the routing table needs root and would affect every process on
+//! the machine, so the scanner does not install routes. Instead it asks the
+//! kernel which local address it would use to reach the gateway and binds
+//! every scan connection to that source address. On a multi-homed host this
+//! selects the interface facing the gateway, and with a source-based policy
+//! rule (e.g. `ip rule add from <SOURCE> table <T>` whose default route is
+//! the gateway) the traffic actually leaves through it.
+
+use std::io; // I/O results
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket}; // Route lookup
+use tokio::net::{TcpSocket, TcpStream}; // Source-bound connections
+
+/// Arbitrary port for the route lookup; no packet is ever sent
+const LOOKUP_PORT: u16 = 9;
+
+/// Returns the local address the kernel would use to reach `gateway`.
+///
+/// Connecting a UDP socket only performs the route lookup; nothing is sent.
+pub fn source_for_gateway(gateway: IpAddr) -> io::Result<IpAddr> {
+    let unspecified = match gateway {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let socket = UdpSocket::bind((unspecified, 0))?;
+    socket.connect((gateway, LOOKUP_PORT))?;
+    Ok(socket.local_addr()?.ip())
+}
+
+/// Opens a TCP connection to `target`, bound to `source` when one is given.
+pub async fn tcp_connect(target: SocketAddr, source: Option<IpAddr>) -> io::Result<TcpStream> {
+    let Some(source) = source else {
+        return TcpStream::connect(target).await;
+    };
+
+    let socket = match target {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    socket.bind(SocketAddr::new(source, 0))?;
+    socket.connect(target).await
+}
@@ -0,0 +1,67 @@
+//! SCTP association probing.
+//!
+//! An SCTP port is open when the target completes the INIT/INIT-ACK
+//! handshake, and closed when it answers with ABORT (reported as a refused
+//! connection). This needs kernel SCTP support (on Linux, the `sctp` module);
+//! no extra privileges are required since a normal one-to-one socket is used.
+
+use socket2::{Domain, Protocol, Socket, Type}; // Raw socket construction
+use std::io; // I/O results
+use std::net::{IpAddr, SocketAddr}; // Target and source addresses
+use tokio::net::TcpStream; // Readiness polling on the connected socket
+
+/// IANA protocol number for SCTP
+const IPPROTO_SCTP: i32 = 132;
+
+/// Creates a non-blocking SCTP socket for `target`'s address family.
+fn socket_for(target: &SocketAddr) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(*target), Type::STREAM, Some(Protocol::from(IPPROTO_SCTP)))?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+/// Checks that this platform can create SCTP sockets at all.
+///
+/// Called before scanning so missing support is a clear error instead of
+/// every port looking closed.
+pub fn check_support(target: &SocketAddr) -> io::Result<()> {
+    socket_for(target).map(drop)
+}
+
+/// Attempts to establish an SCTP association with `target`, from `source`
+/// when one is given.
+///
+/// Returns `Ok(())` once the association is up, or the error the kernel
+/// reported (e.g. connection refused on ABORT). The caller bounds the wait
+/// with its own timeout.
+pub async fn connect(target: SocketAddr, source: Option<IpAddr>) -> io::Result<()> {
+    let socket = socket_for(&target)?;
+    if let Some(source) = source {
+        socket.bind(&SocketAddr::new(source, 0).into())?;
+    }
+
+    match socket.connect(&target.into()) {
+        Ok(()) => return Ok(()),
+        // Non-blocking connect: the handshake continues in the background
+        Err(e) if in_progress(&e) => {}
+        Err(e) => return Err(e),
+    }
+
+    // Tokio only needs a stream-oriented fd to poll for writability, which
+    // signals that the handshake finished one way or the other
+    let stream = TcpStream::from_std(std::net::TcpStream::from(socket))?;
+    stream.writable().await?;
+    match stream.take_error()? {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Whether a non-blocking `connect` error just means "still connecting".
+fn in_progress(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    if e.raw_os_error() == Some(libc::EINPROGRESS) {
+        return true;
+    }
+    e.kind() == io::ErrorKind::WouldBlock
+}
@@ -0,0 +1,75 @@
+//! The `selftest` subcommand.
+//!
+//! Starts a listener on an ephemeral loopback port, scans it together with an
+//! adjacent unused port through the normal scan pipeline, and checks that the
+//! first is reported open and the second closed.
+
+use port_sniffer::{ScanConfig, run_scan}; // The scan pipeline under test
+use std::net::{IpAddr, Ipv4Addr}; // Loopback address
+use tokio::net::TcpListener; // In-process listener
+use tokio::time::Duration; // Connect timeout
+
+/// Subcommand name
+pub const NAME: &str = "selftest";
+/// Subcommand description
+pub const ABOUT: &str = "Scan an in-process listener to check the scanner works in this environment";
+
+/// Runs the self-test, printing PASS/FAIL lines, and returns the process
+/// exit code (0 when every check passed).
+pub async fn run() -> i32 {
+    let loopback = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    // Listener on an ephemeral port; the kernel completes the handshake from
+    // the backlog, so nothing needs to call accept()
+    let listener = match TcpListener::bind((loopback, 0)).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            println!("FAIL: cannot bind a loopback listener: {e}");
+            return 1;
+        }
+    };
+    let open_port = match listener.local_addr() {
+        Ok(addr) => addr.port(),
+        Err(e) => {
+            println!("FAIL: cannot read listener address: {e}");
+            return 1;
+        }
+    };
+
+    let Some(closed_port) = unused_neighbour(loopback, open_port).await else {
+        println!("FAIL: no unused port next to {open_port} to use as the closed probe");
+        return 1;
+    };
+
+    println!("Listening on {loopback}:{open_port}, expecting {closed_port} closed");
+
+    let config = ScanConfig::new(loopback, vec![open_port, closed_port], 2, Duration::from_secs(3));
+    let open_ports: Vec<u16> = run_scan(&config).await.into_iter().flat_map(|result| result.open).collect();
+
+    let checks = [
+        (format!("port {open_port} reported open"), open_ports.contains(&open_port)),
+        (format!("port {closed_port} reported closed"), !open_ports.contains(&closed_port)),
+    ];
+
+    let mut passed = true;
+    for (name, ok) in &checks {
+        println!("{}: {name}", if *ok { "PASS" } else { "FAIL" });
+        passed &= ok;
+    }
+
+    drop(listener);
+    if passed { 0 } else { 1 }
+}
+
+/// Finds a port directly above or below `port` that nothing is listening on.
+///
+/// A port counts as unused if it can be bound; the probe listener is dropped
+/// straight away so connections to it are refused.
+async fn unused_neighbour(addr: IpAddr, port: u16) -> Option<u16> {
+    for candidate in [port.checked_add(1), port.checked_sub(1)].into_iter().flatten() {
+        if candidate != 0 && TcpListener::bind((addr, candidate)).await.is_ok() {
+            return Some(candidate);
+        }
+    }
+    None
+}
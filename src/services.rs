@@ -0,0 +1,76 @@
+//! Well-known service names for common TCP ports.
+//!
+//! Names follow the IANA service name registry, or the name in common use
+//! where the registered one is obscure (e.g. `oracle` for 1521). They
+//! describe what usually runs on a port, not what was detected there (see
+//! `identify`).
+
+/// Conventional service name for TCP `port`, for the common ports only.
+pub fn service_name(port: u16) -> Option<&'static str> {
+    let name = match port {
+        20 => "ftp-data",
+        21 => "ftp",
+        22 => "ssh",
+        23 => "telnet",
+        25 => "smtp",
+        53 => "domain",
+        67 => "bootps",
+        69 => "tftp",
+        79 => "finger",
+        80 => "http",
+        88 => "kerberos",
+        110 => "pop3",
+        111 => "sunrpc",
+        119 => "nntp",
+        123 => "ntp",
+        135 => "msrpc",
+        139 => "netbios-ssn",
+        143 => "imap",
+        161 => "snmp",
+        179 => "bgp",
+        389 => "ldap",
+        443 => "https",
+        445 => "microsoft-ds",
+        465 => "submissions",
+        514 => "shell",
+        515 => "printer",
+        543 => "klogin",
+        544 => "kshell",
+        548 => "afp",
+        554 => "rtsp",
+        587 => "submission",
+        631 => "ipp",
+        636 => "ldaps",
+        873 => "rsync",
+        990 => "ftps",
+        993 => "imaps",
+        995 => "pop3s",
+        1080 => "socks",
+        1433 => "ms-sql-s",
+        1521 => "oracle",
+        1723 => "pptp",
+        1883 => "mqtt",
+        2049 => "nfs",
+        2375 => "docker",
+        2376 => "docker-s",
+        3306 => "mysql",
+        3389 => "ms-wbt-server",
+        5060 => "sip",
+        5432 => "postgresql",
+        5672 => "amqp",
+        5900 => "vnc",
+        5985 => "wsman",
+        5986 => "wsmans",
+        6379 => "redis",
+        6443 => "sun-sr-https",
+        8080 => "http-alt",
+        8443 => "https-alt",
+        9000 => "cslistener",
+        9092 => "kafka",
+        9200 => "elasticsearch",
+        11211 => "memcache",
+        27017 => "mongodb",
+        _ => return None,
+    };
+    Some(name)
+}
@@ -0,0 +1,178 @@
+//! TLS handshake probe for `--tls-probe`.
+//!
+//! Each open port gets a fresh connection and a TLS 1.2/1.3 ClientHello. A
+//! completed handshake tells that the port speaks TLS, whatever its number,
+//! and yields the negotiated version and the common name of the server
+//! certificate. Certificates are not validated: self-signed and expired
+//! ones are exactly what an audit wants to see. Ports that answer with
+//! anything else are reported as not speaking TLS.
+
+use port_sniffer::route; // Source-bound connections
+use serde::Serialize; // Probe results in JSON output
+use std::net::{IpAddr, SocketAddr}; // Probe targets and source address
+use std::sync::{Arc, LazyLock}; // Shared client configuration
+use tokio::time::{Duration, timeout}; // Bound the connect and the handshake
+use tokio_rustls::TlsConnector; // Async TLS handshakes
+use tokio_rustls::rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier}; // Accepting any certificate
+use tokio_rustls::rustls::crypto::{CryptoProvider, ring, verify_tls12_signature, verify_tls13_signature}; // Handshake cryptography
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime}; // Certificates and peer names
+use tokio_rustls::rustls::{ClientConfig, DigitallySignedStruct, ProtocolVersion, SignatureScheme}; // Client settings
+
+/// Longest common name kept, in characters
+const MAX_NAME: usize = 120;
+/// DER encoding of the commonName attribute type (OID 2.5.4.3)
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+/// What the TLS handshake on an open port showed
+#[derive(Serialize)]
+pub struct TlsInfo {
+    /// Whether the handshake completed
+    pub handshake: bool,
+    /// Negotiated protocol version, e.g. `TLSv1.3`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<&'static str>,
+    /// Common name in the subject of the server certificate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cn: Option<String>,
+}
+
+impl TlsInfo {
+    /// Result for a port that did not complete a handshake
+    fn none() -> Self {
+        Self { handshake: false, version: None, cn: None }
+    }
+}
+
+/// Client configuration shared by all probes
+static CLIENT: LazyLock<Arc<ClientConfig>> = LazyLock::new(|| {
+    let provider = Arc::new(ring::default_provider());
+    let config = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .expect("ring supports the default protocol versions")
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AnyCertificate(provider)))
+        .with_no_client_auth();
+    Arc::new(config)
+});
+
+/// Attempts a TLS handshake with `target`, connecting from `source` if
+/// given. The connect is bounded by `connect_timeout` and the handshake by
+/// `read_timeout`; a refusal, timeout or non-TLS answer all yield a result
+/// without handshake.
+pub async fn probe(target: SocketAddr, source: Option<IpAddr>, connect_timeout: Duration, read_timeout: Duration) -> TlsInfo {
+    let handshake = async {
+        let stream = timeout(connect_timeout, route::tcp_connect(target, source)).await.ok()?.ok()?;
+        let server_name = ServerName::IpAddress(target.ip().into());
+        let tls = timeout(read_timeout, TlsConnector::from(CLIENT.clone()).connect(server_name, stream)).await.ok()?.ok()?;
+        let (_, connection) = tls.get_ref();
+        let version = connection.protocol_version().map(version_name);
+        let cn = connection.peer_certificates().and_then(|chain| chain.first()).and_then(|cert| common_name(cert));
+        Some(TlsInfo { handshake: true, version, cn })
+    };
+    handshake.await.unwrap_or_else(TlsInfo::none)
+}
+
+/// Name of a TLS version as usually written
+fn version_name(version: ProtocolVersion) -> &'static str {
+    match version {
+        ProtocolVersion::TLSv1_3 => "TLSv1.3",
+        ProtocolVersion::TLSv1_2 => "TLSv1.2",
+        _ => "TLS",
+    }
+}
+
+/// Subject common name of a DER certificate, if present and readable.
+///
+/// Walks `Certificate > TBSCertificate > subject` directly instead of
+/// parsing the whole certificate: the subject is the sixth field of the
+/// TBSCertificate when the optional `[0]` version is present, the fifth
+/// otherwise.
+fn common_name(cert: &CertificateDer) -> Option<String> {
+    let (_, certificate, _) = read_tlv(cert)?;
+    let (_, mut tbs, _) = read_tlv(certificate)?;
+    if tbs.first() == Some(&0xa0) {
+        tbs = read_tlv(tbs)?.2;
+    }
+    // serialNumber, signature, issuer, validity
+    for _ in 0..4 {
+        tbs = read_tlv(tbs)?.2;
+    }
+    let (_, mut subject, _) = read_tlv(tbs)?;
+
+    // Name ::= SEQUENCE OF SET OF SEQUENCE { type OID, value }
+    while !subject.is_empty() {
+        let (_, mut set, rest) = read_tlv(subject)?;
+        subject = rest;
+        while !set.is_empty() {
+            let (_, attribute, rest) = read_tlv(set)?;
+            set = rest;
+            let (tag, oid, value) = read_tlv(attribute)?;
+            if tag == 0x06 && oid == OID_COMMON_NAME {
+                let (_, name, _) = read_tlv(value)?;
+                let name = String::from_utf8_lossy(name);
+                return Some(name.chars().filter(|c| !c.is_control()).take(MAX_NAME).collect());
+            }
+        }
+    }
+    None
+}
+
+/// Splits one DER element off `data`: its tag, its contents and the bytes
+/// after it.
+fn read_tlv(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = data.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (usize::from(first), rest)
+    } else {
+        // Long form: the low bits give the number of length bytes
+        let count = usize::from(first & 0x7f);
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let len = rest[..count].iter().fold(0usize, |len, &b| (len << 8) | usize::from(b));
+        (len, &rest[count..])
+    };
+    (rest.len() >= len).then(|| (tag, &rest[..len], &rest[len..]))
+}
+
+/// Certificate verifier that accepts every certificate, so the handshake
+/// completes with self-signed, expired or mismatched certificates. The
+/// handshake signatures are still checked against the presented key.
+#[derive(Debug)]
+struct AnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
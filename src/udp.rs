@@ -0,0 +1,92 @@
+//! UDP port probing.
+//!
+//! UDP has no handshake, so a probe sends one datagram and waits. A reply
+//! means the port is open; an ICMP port-unreachable (reported on a
+//! connected socket as a refused connection) means it is closed. Silence
+//! is ambiguous: the service may have ignored the datagram, or a firewall
+//! dropped it, so such ports are `open|filtered`.
+
+use std::io; // I/O results
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr}; // Target and source addresses
+use tokio::io::Interest; // Waiting for replies and ICMP errors
+use tokio::net::UdpSocket; // Async UDP sockets
+
+/// Largest reply read; only its arrival matters
+const MAX_REPLY: usize = 512;
+
+/// DNS query for the root name servers (`. IN NS`), recursion desired
+const DNS_QUERY: &[u8] = &[
+    0x50, 0x53, // id
+    0x01, 0x00, // flags: standard query, RD
+    0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // 1 question
+    0x00, // root name
+    0x00, 0x02, 0x00, 0x01, // NS, IN
+];
+
+/// NTP v3 client request (mode 3), all other fields zero
+const NTP_REQUEST: &[u8] = &[
+    0x1b, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, //
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, //
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+/// SNMPv2c GetRequest for sysDescr.0 with community `public`
+const SNMP_GET: &[u8] = &[
+    0x30, 0x29, // message
+    0x02, 0x01, 0x01, // version 2c
+    0x04, 0x06, b'p', b'u', b'b', b'l', b'i', b'c', // community
+    0xa0, 0x1c, // GetRequest PDU
+    0x02, 0x04, 0x00, 0x00, 0x00, 0x01, // request id
+    0x02, 0x01, 0x00, 0x02, 0x01, 0x00, // error status, error index
+    0x30, 0x0e, 0x30, 0x0c, // variable bindings
+    0x06, 0x08, 0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, 0x01, 0x00, // 1.3.6.1.2.1.1.1.0
+    0x05, 0x00, // null value
+];
+
+/// Datagram most likely to get an answer from the service usually on
+/// `port`; other ports get an empty datagram.
+fn payload_for(port: u16) -> &'static [u8] {
+    match port {
+        53 => DNS_QUERY,
+        123 => NTP_REQUEST,
+        161 => SNMP_GET,
+        _ => &[],
+    }
+}
+
+/// Sends a probe to `target`, from `source` when one is given, and waits for
+/// the outcome.
+///
+/// Returns `Ok(())` when the target replied and the refused-connection error
+/// when it answered with ICMP port-unreachable. Without either this never
+/// completes; the caller bounds the wait with its own timeout.
+pub async fn probe(target: SocketAddr, source: Option<IpAddr>) -> io::Result<()> {
+    let local = source.unwrap_or(match target {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    });
+    let socket = UdpSocket::bind((local, 0)).await?;
+
+    // Connecting lets the kernel report ICMP errors on this socket
+    socket.connect(target).await?;
+    socket.send(payload_for(target.port())).await?;
+
+    // An ICMP error only raises error readiness, which a plain `recv` would
+    // never wake up for, so wait for either and collect the socket error
+    let mut buf = [0; MAX_REPLY];
+    loop {
+        let ready = socket.ready(Interest::READABLE | Interest::ERROR).await?;
+        if ready.is_error()
+            && let Some(e) = socket.take_error()?
+        {
+            return Err(e);
+        }
+        if ready.is_readable() {
+            match socket.try_recv(&mut buf) {
+                Ok(_) => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}